@@ -0,0 +1,5 @@
+ALTER TABLE users ADD COLUMN role TEXT NOT NULL DEFAULT 'contributor'
+    CHECK (role IN ('viewer', 'contributor', 'editor', 'admin'));
+
+-- The earliest account administers the server
+UPDATE users SET role = 'admin' WHERE id = (SELECT MIN(id) FROM users);
\ No newline at end of file
@@ -0,0 +1 @@
+ALTER TABLE sessions DROP COLUMN kind;
\ No newline at end of file
@@ -0,0 +1,3 @@
+DROP INDEX IF EXISTS idx_quotes_created_by;
+ALTER TABLE quotes DROP COLUMN updated_by;
+ALTER TABLE quotes DROP COLUMN created_by;
\ No newline at end of file
@@ -0,0 +1,6 @@
+-- Accounts that created and last changed each quote; quotes from before
+-- ownership was tracked, or whose author was removed, have none
+ALTER TABLE quotes ADD COLUMN created_by INTEGER REFERENCES users (id) ON DELETE SET NULL;
+ALTER TABLE quotes ADD COLUMN updated_by INTEGER REFERENCES users (id) ON DELETE SET NULL;
+
+CREATE INDEX IF NOT EXISTS idx_quotes_created_by ON quotes (created_by);
\ No newline at end of file
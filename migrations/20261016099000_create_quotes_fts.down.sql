@@ -0,0 +1,7 @@
+DROP TRIGGER IF EXISTS quotes_fts_tag_delete;
+DROP TRIGGER IF EXISTS quotes_fts_tag_update;
+DROP TRIGGER IF EXISTS quotes_fts_tag_insert;
+DROP TRIGGER IF EXISTS quotes_fts_delete;
+DROP TRIGGER IF EXISTS quotes_fts_update;
+DROP TRIGGER IF EXISTS quotes_fts_insert;
+DROP TABLE IF EXISTS quotes_fts;
\ No newline at end of file
@@ -0,0 +1,47 @@
+-- Full-text index over quote text, source and tag names. Its rowid is the
+-- quote ID; the triggers below keep it in sync with quotes and tags.
+CREATE VIRTUAL TABLE IF NOT EXISTS quotes_fts USING fts5 (
+    quote,
+    source,
+    tags,
+    tokenize = 'porter unicode61 remove_diacritics 2',
+    prefix = '2 3'
+);
+
+INSERT INTO quotes_fts (rowid, quote, source, tags)
+SELECT id, quote, source,
+    COALESCE((SELECT group_concat(name, ' ') FROM tags WHERE tags.quote_id = quotes.id), '')
+FROM quotes;
+
+CREATE TRIGGER IF NOT EXISTS quotes_fts_insert AFTER INSERT ON quotes BEGIN
+    INSERT INTO quotes_fts (rowid, quote, source, tags) VALUES (new.id, new.quote, new.source, '');
+END;
+
+CREATE TRIGGER IF NOT EXISTS quotes_fts_update AFTER UPDATE OF quote, source ON quotes BEGIN
+    UPDATE quotes_fts SET quote = new.quote, source = new.source WHERE rowid = new.id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS quotes_fts_delete AFTER DELETE ON quotes BEGIN
+    DELETE FROM quotes_fts WHERE rowid = old.id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS quotes_fts_tag_insert AFTER INSERT ON tags BEGIN
+    UPDATE quotes_fts
+    SET tags = COALESCE((SELECT group_concat(name, ' ') FROM tags WHERE quote_id = new.quote_id), '')
+    WHERE rowid = new.quote_id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS quotes_fts_tag_update AFTER UPDATE OF quote_id, name ON tags BEGIN
+    UPDATE quotes_fts
+    SET tags = COALESCE((SELECT group_concat(name, ' ') FROM tags WHERE quote_id = old.quote_id), '')
+    WHERE rowid = old.quote_id;
+    UPDATE quotes_fts
+    SET tags = COALESCE((SELECT group_concat(name, ' ') FROM tags WHERE quote_id = new.quote_id), '')
+    WHERE rowid = new.quote_id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS quotes_fts_tag_delete AFTER DELETE ON tags BEGIN
+    UPDATE quotes_fts
+    SET tags = COALESCE((SELECT group_concat(name, ' ') FROM tags WHERE quote_id = old.quote_id), '')
+    WHERE rowid = old.quote_id;
+END;
\ No newline at end of file
@@ -0,0 +1,42 @@
+DROP TRIGGER IF EXISTS quotes_fts_tag_update;
+DROP TRIGGER IF EXISTS quotes_fts_tag_delete;
+DROP TRIGGER IF EXISTS quotes_fts_tag_insert;
+
+CREATE TABLE IF NOT EXISTS quote_tags_old (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    quote_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (quote_id) REFERENCES quotes (id) ON DELETE CASCADE
+);
+
+INSERT INTO quote_tags_old (quote_id, name, created_at, updated_at)
+SELECT quote_tags.quote_id, tags.name, quote_tags.created_at, tags.updated_at
+FROM quote_tags
+INNER JOIN tags ON tags.id = quote_tags.tag_id;
+
+DROP TABLE quote_tags;
+DROP TABLE tags;
+ALTER TABLE quote_tags_old RENAME TO tags;
+
+CREATE TRIGGER IF NOT EXISTS quotes_fts_tag_insert AFTER INSERT ON tags BEGIN
+    UPDATE quotes_fts
+    SET tags = COALESCE((SELECT group_concat(name, ' ') FROM tags WHERE quote_id = new.quote_id), '')
+    WHERE rowid = new.quote_id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS quotes_fts_tag_update AFTER UPDATE OF quote_id, name ON tags BEGIN
+    UPDATE quotes_fts
+    SET tags = COALESCE((SELECT group_concat(name, ' ') FROM tags WHERE quote_id = old.quote_id), '')
+    WHERE rowid = old.quote_id;
+    UPDATE quotes_fts
+    SET tags = COALESCE((SELECT group_concat(name, ' ') FROM tags WHERE quote_id = new.quote_id), '')
+    WHERE rowid = new.quote_id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS quotes_fts_tag_delete AFTER DELETE ON tags BEGIN
+    UPDATE quotes_fts
+    SET tags = COALESCE((SELECT group_concat(name, ' ') FROM tags WHERE quote_id = old.quote_id), '')
+    WHERE rowid = old.quote_id;
+END;
\ No newline at end of file
@@ -0,0 +1,80 @@
+-- Store each tag name once and link it to its quotes through quote_tags
+DROP TRIGGER IF EXISTS quotes_fts_tag_delete;
+DROP TRIGGER IF EXISTS quotes_fts_tag_update;
+DROP TRIGGER IF EXISTS quotes_fts_tag_insert;
+
+ALTER TABLE tags RENAME TO quote_tags_old;
+
+CREATE TABLE IF NOT EXISTS tags (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL UNIQUE,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE IF NOT EXISTS quote_tags (
+    quote_id INTEGER NOT NULL,
+    tag_id INTEGER NOT NULL,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (quote_id, tag_id),
+    FOREIGN KEY (quote_id) REFERENCES quotes (id) ON DELETE CASCADE,
+    FOREIGN KEY (tag_id) REFERENCES tags (id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_quote_tags_tag_id ON quote_tags (tag_id);
+
+INSERT INTO tags (name, created_at, updated_at)
+SELECT name, MIN(created_at), MAX(updated_at)
+FROM quote_tags_old
+GROUP BY name;
+
+INSERT INTO quote_tags (quote_id, tag_id, created_at)
+SELECT quote_tags_old.quote_id, tags.id, MIN(quote_tags_old.created_at)
+FROM quote_tags_old
+INNER JOIN tags ON tags.name = quote_tags_old.name
+GROUP BY quote_tags_old.quote_id, tags.id;
+
+DROP TABLE quote_tags_old;
+
+-- Duplicate names on a quote were indexed more than once
+UPDATE quotes_fts
+SET tags = COALESCE((
+    SELECT group_concat(tags.name, ' ')
+    FROM quote_tags
+    INNER JOIN tags ON tags.id = quote_tags.tag_id
+    WHERE quote_tags.quote_id = quotes_fts.rowid
+), '');
+
+-- Keep the tags column of the full-text index in sync with the links and names
+CREATE TRIGGER IF NOT EXISTS quotes_fts_tag_insert AFTER INSERT ON quote_tags BEGIN
+    UPDATE quotes_fts
+    SET tags = COALESCE((
+        SELECT group_concat(tags.name, ' ')
+        FROM quote_tags
+        INNER JOIN tags ON tags.id = quote_tags.tag_id
+        WHERE quote_tags.quote_id = new.quote_id
+    ), '')
+    WHERE rowid = new.quote_id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS quotes_fts_tag_delete AFTER DELETE ON quote_tags BEGIN
+    UPDATE quotes_fts
+    SET tags = COALESCE((
+        SELECT group_concat(tags.name, ' ')
+        FROM quote_tags
+        INNER JOIN tags ON tags.id = quote_tags.tag_id
+        WHERE quote_tags.quote_id = old.quote_id
+    ), '')
+    WHERE rowid = old.quote_id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS quotes_fts_tag_update AFTER UPDATE OF name ON tags BEGIN
+    UPDATE quotes_fts
+    SET tags = COALESCE((
+        SELECT group_concat(tags.name, ' ')
+        FROM quote_tags
+        INNER JOIN tags ON tags.id = quote_tags.tag_id
+        WHERE quote_tags.quote_id = quotes_fts.rowid
+    ), '')
+    WHERE rowid IN (SELECT quote_id FROM quote_tags WHERE tag_id = new.id);
+END;
\ No newline at end of file
@@ -0,0 +1,5 @@
+DROP INDEX IF EXISTS idx_quotes_author_id;
+ALTER TABLE quotes DROP COLUMN author_id;
+DROP INDEX IF EXISTS idx_author_aliases_author_id;
+DROP TABLE IF EXISTS author_aliases;
+DROP TABLE IF EXISTS authors;
\ No newline at end of file
@@ -0,0 +1,27 @@
+-- People quotes are attributed to. quotes.source keeps the attribution as
+-- written; author_id links it to the author it names.
+CREATE TABLE IF NOT EXISTS authors (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL UNIQUE COLLATE NOCASE,
+    birth_year INTEGER,
+    death_year INTEGER,
+    bio TEXT,
+    links TEXT NOT NULL DEFAULT '[]',
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Other spellings of an author's name, such as "Einstein" or "A. Einstein"
+CREATE TABLE IF NOT EXISTS author_aliases (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    author_id INTEGER NOT NULL,
+    alias TEXT NOT NULL UNIQUE COLLATE NOCASE,
+    FOREIGN KEY (author_id) REFERENCES authors (id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_author_aliases_author_id ON author_aliases (author_id);
+
+-- Existing quotes are linked by the server on startup
+ALTER TABLE quotes ADD COLUMN author_id INTEGER REFERENCES authors (id) ON DELETE SET NULL;
+
+CREATE INDEX IF NOT EXISTS idx_quotes_author_id ON quotes (author_id);
\ No newline at end of file
@@ -0,0 +1,5 @@
+ALTER TABLE quotes DROP COLUMN note;
+ALTER TABLE quotes DROP COLUMN url;
+ALTER TABLE quotes DROP COLUMN page;
+ALTER TABLE quotes DROP COLUMN year;
+ALTER TABLE quotes DROP COLUMN work;
\ No newline at end of file
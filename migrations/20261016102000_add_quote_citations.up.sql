@@ -0,0 +1,6 @@
+-- Where a quote comes from, beyond who said it
+ALTER TABLE quotes ADD COLUMN work TEXT;
+ALTER TABLE quotes ADD COLUMN year INTEGER;
+ALTER TABLE quotes ADD COLUMN page TEXT;
+ALTER TABLE quotes ADD COLUMN url TEXT;
+ALTER TABLE quotes ADD COLUMN note TEXT;
\ No newline at end of file
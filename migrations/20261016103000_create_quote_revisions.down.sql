@@ -0,0 +1 @@
+DROP TABLE IF EXISTS quote_revisions;
\ No newline at end of file
@@ -0,0 +1,29 @@
+-- Every state a quote has been in, numbered per quote from 1. A revision's
+-- "before" is the revision preceding it.
+CREATE TABLE IF NOT EXISTS quote_revisions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    quote_id INTEGER NOT NULL,
+    revision INTEGER NOT NULL,
+    action TEXT NOT NULL,
+    restored_from INTEGER,
+    quote TEXT NOT NULL,
+    source TEXT NOT NULL,
+    author_id INTEGER,
+    work TEXT,
+    year INTEGER,
+    page TEXT,
+    url TEXT,
+    note TEXT,
+    tags TEXT NOT NULL DEFAULT '[]',
+    user_id INTEGER REFERENCES users (id) ON DELETE SET NULL,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (quote_id, revision),
+    FOREIGN KEY (quote_id) REFERENCES quotes (id) ON DELETE CASCADE
+);
+
+-- Existing quotes start their history as they are now
+INSERT INTO quote_revisions (quote_id, revision, action, quote, source, author_id, work, year, page, url, note, tags, user_id, created_at)
+SELECT id, 1, 'create', quote, source, author_id, work, year, page, url, note,
+    (SELECT json_group_array(name) FROM (SELECT tags.name FROM quote_tags INNER JOIN tags ON tags.id = quote_tags.tag_id WHERE quote_tags.quote_id = quotes.id ORDER BY tags.name)),
+    COALESCE(updated_by, created_by), updated_at
+FROM quotes;
\ No newline at end of file
@@ -0,0 +1,6 @@
+-- Deleted quotes stay in the trash, with their tags and revisions, until they
+-- are restored or purged
+ALTER TABLE quotes ADD COLUMN deleted_at DATETIME;
+ALTER TABLE quotes ADD COLUMN deleted_by INTEGER REFERENCES users (id) ON DELETE SET NULL;
+
+CREATE INDEX IF NOT EXISTS idx_quotes_deleted_at ON quotes (deleted_at);
\ No newline at end of file
@@ -0,0 +1,3 @@
+-- Every change to a quote increments its version, which the API sends as the
+-- quote's ETag so conflicting updates can be refused
+ALTER TABLE quotes ADD COLUMN version INTEGER NOT NULL DEFAULT 1;
\ No newline at end of file
@@ -0,0 +1 @@
+ALTER TABLE sessions DROP COLUMN second_factor;
\ No newline at end of file
@@ -0,0 +1,2 @@
+-- Whether the login that started the session passed a TOTP code
+ALTER TABLE sessions ADD COLUMN second_factor BOOLEAN NOT NULL DEFAULT 0;
\ No newline at end of file
@@ -5,23 +5,141 @@
 //!
 use crate::{
     AppState,
-    authjwt::{self, Claims, Registration},
-    db::{self, CreateQuoteRequest, QuoteWithTags, UpdateQuoteRequest},
+    authjwt::{
+        self, Admin, AuthError, Claims, Contributor, Credentials, Editor, KeyRingStatus,
+        RefreshRequest, Registration, RequireRole, Role, Scope,
+    },
+    db::{
+        self, ApiKeyInfo, Author, AuthorRequest, Citation, CreateApiKeyRequest, CreateQuoteRequest,
+        CreatedApiKey, FieldChange, MergeTagRequest, PatchQuoteRequest, QuoteRevision,
+        QuoteWithTags, RenameTagRequest, RevisionAction, RevisionDiff, TagWithCount, TagsPatch,
+        UpdateQuoteRequest, UpdateUserRequest, UserProfile,
+    },
+    oidc::{self, OidcError},
+    throttle::{self, ClientIp},
+    totp::{self, RecoveryCodes, TotpChallenge, TotpCodeRequest, TotpEnrollment, TotpLoginRequest},
 };
 use axum::{
     extract::{Path, Query, State},
-    http::StatusCode,
-    response::{IntoResponse, Json},
-    routing::{get, post},
+    http::{HeaderMap, StatusCode, header},
+    response::{AppendHeaders, IntoResponse, Json, Redirect},
+    routing::{delete, get, post, put},
 };
+use chrono::{DateTime, Utc};
 use serde::Deserialize;
 use utoipa::{
     IntoParams, Modify, OpenApi,
-    openapi::security::{Http, HttpAuthScheme, SecurityScheme},
+    openapi::security::{ApiKey, ApiKeyValue, Http, HttpAuthScheme, SecurityScheme},
 };
 
-#[derive(Debug, Deserialize, IntoParams, utoipa::ToSchema)]
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct OidcLoginParams {
+    /// Web UI page to return to after logging in
+    #[param(example = "/quotes")]
+    pub return_to: Option<String>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct OidcCallback {
+    /// Authorization code issued by the identity provider
+    pub code: Option<String>,
+    /// Login state echoed back by the identity provider
+    pub state: Option<String>,
+    /// Error code when the login was refused or cancelled
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct DiffParams {
+    /// Revision to compare from; defaults to the one before `to`
+    #[param(example = 1)]
+    pub from: Option<i64>,
+    /// Revision to compare to; defaults to the latest
+    #[param(example = 2)]
+    pub to: Option<i64>,
+}
+
+/// Page size for quote listings when no `limit` is given
+pub const DEFAULT_PAGE_SIZE: i64 = 20;
+
+/// Largest page size a quote listing may ask for
+pub const MAX_PAGE_SIZE: i64 = 100;
+
+/// Field quote listings are sorted by
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, utoipa::ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum QuoteSort {
+    /// Creation time, newest first by default
+    Created,
+    /// Time of the last update, most recent first by default
+    Updated,
+    /// Source, alphabetically by default
+    Source,
+    /// Quote ID, lowest first by default
+    Id,
+    /// Full-text search rank, best match first by default; the default with `q`
+    Relevance,
+}
+
+impl QuoteSort {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            QuoteSort::Created => "created",
+            QuoteSort::Updated => "updated",
+            QuoteSort::Source => "source",
+            QuoteSort::Id => "id",
+            QuoteSort::Relevance => "relevance",
+        }
+    }
+
+    fn default_order(&self) -> SortOrder {
+        match self {
+            QuoteSort::Source | QuoteSort::Id => SortOrder::Asc,
+            QuoteSort::Created | QuoteSort::Updated | QuoteSort::Relevance => SortOrder::Desc,
+        }
+    }
+}
+
+/// Direction of a sort
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, utoipa::ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+impl SortOrder {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SortOrder::Asc => "asc",
+            SortOrder::Desc => "desc",
+        }
+    }
+}
+
+/// Read an empty query parameter, as sent by a form's default option, as absent
+fn empty_as_none<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: serde::de::DeserializeOwned,
+{
+    use serde::de::IntoDeserializer;
+
+    match Option::<String>::deserialize(deserializer)?
+        .as_deref()
+        .map(str::trim)
+    {
+        None | Some("") => Ok(None),
+        Some(value) => T::deserialize(value.into_deserializer()).map(Some),
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize, IntoParams, utoipa::ToSchema)]
 pub struct SearchParams {
+    /// Full-text search over quote text, source and tags, ranked by relevance. Supports
+    /// `AND`/`OR`/`NOT`, `"exact phrases"`, `prefix*` and `source:` column filters.
+    #[param(example = "imagin* AND knowledge")]
+    pub q: Option<String>,
     /// Search within quote text
     #[param(example = "imagination")]
     pub quote: Option<String>,
@@ -31,6 +149,141 @@ pub struct SearchParams {
     /// Search within tags
     #[param(example = "creativity")]
     pub tag: Option<String>,
+    /// Only quotes carrying all of these tags, as a comma-separated list of exact names
+    #[param(example = "science,imagination")]
+    pub tags: Option<String>,
+    /// Leave out quotes carrying any of these tags, as a comma-separated list of exact names
+    #[param(example = "humor")]
+    pub exclude_tags: Option<String>,
+    /// Only quotes linked to the author with this ID
+    #[param(example = 1)]
+    pub author_id: Option<i64>,
+    /// Only quotes created by the user with this ID
+    #[param(example = 1)]
+    pub created_by: Option<i64>,
+    /// Only quotes created at or after this time
+    #[param(value_type = Option<String>, format = DateTime, example = "2024-01-01T00:00:00Z")]
+    pub created_after: Option<DateTime<Utc>>,
+    /// Only quotes created before this time
+    #[param(value_type = Option<String>, format = DateTime, example = "2025-01-01T00:00:00Z")]
+    pub created_before: Option<DateTime<Utc>>,
+    /// Field to sort by: `created` (default), `updated`, `source`, `id`, or `relevance` (default with `q`)
+    #[serde(default, deserialize_with = "empty_as_none")]
+    #[param(inline, example = "source")]
+    pub sort: Option<QuoteSort>,
+    /// Sort direction, `asc` or `desc`; the default depends on `sort`
+    #[serde(default, deserialize_with = "empty_as_none")]
+    #[param(inline, example = "asc")]
+    pub order: Option<SortOrder>,
+    /// Number of quotes per page, at most 100
+    #[param(example = 20, maximum = 100, minimum = 1)]
+    pub limit: Option<i64>,
+    /// Number of matching quotes to skip
+    #[param(example = 0, minimum = 0)]
+    pub offset: Option<i64>,
+}
+
+impl SearchParams {
+    /// Page size, defaulting to [`DEFAULT_PAGE_SIZE`] and capped at [`MAX_PAGE_SIZE`]
+    pub fn limit(&self) -> i64 {
+        self.limit
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .clamp(1, MAX_PAGE_SIZE)
+    }
+
+    pub fn offset(&self) -> i64 {
+        self.offset.unwrap_or(0).max(0)
+    }
+
+    /// Names in a comma-separated tag list parameter
+    pub fn tag_list(tags: &Option<String>) -> Vec<String> {
+        tags.as_deref()
+            .unwrap_or_default()
+            .split(',')
+            .map(|tag| tag.trim().to_string())
+            .filter(|tag| !tag.is_empty())
+            .collect()
+    }
+
+    /// Whether this is a full-text search
+    pub fn is_full_text(&self) -> bool {
+        self.q.as_ref().is_some_and(|q| !q.trim().is_empty())
+    }
+
+    /// Field to sort by; relevance only applies to full-text searches
+    pub fn sort(&self) -> QuoteSort {
+        match self.sort {
+            Some(QuoteSort::Relevance) | None if self.is_full_text() => QuoteSort::Relevance,
+            Some(QuoteSort::Relevance) | None => QuoteSort::Created,
+            Some(sort) => sort,
+        }
+    }
+
+    pub fn order(&self) -> SortOrder {
+        self.order.unwrap_or_else(|| self.sort().default_order())
+    }
+
+    /// Query string repeating this search for the page starting at `offset`
+    pub fn page_query(&self, offset: i64) -> String {
+        let mut pairs = Vec::new();
+        for (name, value) in [
+            ("q", &self.q),
+            ("quote", &self.quote),
+            ("source", &self.source),
+            ("tag", &self.tag),
+            ("tags", &self.tags),
+            ("exclude_tags", &self.exclude_tags),
+        ] {
+            if let Some(value) = value.as_deref().filter(|value| !value.trim().is_empty()) {
+                pairs.push(format!("{name}={}", totp::percent_encode(value)));
+            }
+        }
+        if let Some(author_id) = self.author_id {
+            pairs.push(format!("author_id={author_id}"));
+        }
+        if let Some(user_id) = self.created_by {
+            pairs.push(format!("created_by={user_id}"));
+        }
+        for (name, time) in [
+            ("created_after", self.created_after),
+            ("created_before", self.created_before),
+        ] {
+            if let Some(time) = time {
+                pairs.push(format!(
+                    "{name}={}",
+                    totp::percent_encode(&time.to_rfc3339())
+                ));
+            }
+        }
+        if let Some(sort) = self.sort {
+            pairs.push(format!("sort={}", sort.as_str()));
+        }
+        if let Some(order) = self.order {
+            pairs.push(format!("order={}", order.as_str()));
+        }
+        pairs.push(format!("limit={}", self.limit()));
+        pairs.push(format!("offset={offset}"));
+        pairs.join("&")
+    }
+}
+
+/// Offsets of the pages around the current one in a quote listing
+#[derive(Debug, PartialEq, Eq)]
+pub struct PageOffsets {
+    pub prev: Option<i64>,
+    pub next: Option<i64>,
+    pub last: i64,
+}
+
+impl PageOffsets {
+    pub fn new(params: &SearchParams, total: i64) -> Self {
+        let (limit, offset) = (params.limit(), params.offset());
+        Self {
+            prev: (offset > 0).then(|| (offset - limit).max(0)),
+            next: (offset + limit < total).then_some(offset + limit),
+            last: (total - 1).max(0) / limit * limit,
+        }
+    }
 }
 
 /// OpenAPI documentation for the Quotes API
@@ -42,15 +295,54 @@ pub struct SearchParams {
         get_random_quote,
         create_quote,
         update_quote,
+        patch_quote,
         delete_quote,
-        register
+        get_my_quotes,
+        list_revisions,
+        get_revision,
+        diff_revisions,
+        restore_revision,
+        list_trash,
+        restore_quote,
+        purge_quote,
+        list_tags,
+        rename_tag,
+        merge_tag,
+        list_authors,
+        get_author,
+        create_author,
+        update_author,
+        delete_author,
+        login,
+        login_totp,
+        refresh,
+        logout,
+        register,
+        oidc_login,
+        oidc_callback,
+        jwks,
+        reload_keys,
+        list_api_keys,
+        create_api_key,
+        revoke_api_key,
+        begin_totp,
+        confirm_totp,
+        disable_totp,
+        list_users,
+        update_user
     ),
     components(
-        schemas(QuoteWithTags, CreateQuoteRequest, UpdateQuoteRequest, Registration, authjwt::AuthBody, SearchParams)
+        schemas(QuoteWithTags, Citation, CreateQuoteRequest, UpdateQuoteRequest, PatchQuoteRequest, TagsPatch, QuoteRevision, RevisionAction, RevisionDiff, FieldChange, TagWithCount, RenameTagRequest, MergeTagRequest, Author, AuthorRequest, Registration, Credentials, RefreshRequest, UserProfile, UpdateUserRequest, Role, KeyRingStatus, Scope, ApiKeyInfo, CreateApiKeyRequest, CreatedApiKey, authjwt::AuthBody, SearchParams, TotpChallenge, TotpLoginRequest, TotpEnrollment, TotpCodeRequest, RecoveryCodes)
     ),
     tags(
         (name = "quotes", description = "Quote management endpoints"),
-        (name = "auth", description = "Authentication endpoints")
+        (name = "trash", description = "Endpoints for deleted quotes"),
+        (name = "tags", description = "Tag management endpoints"),
+        (name = "authors", description = "Author management endpoints"),
+        (name = "auth", description = "Authentication endpoints"),
+        (name = "users", description = "User administration endpoints"),
+        (name = "api-keys", description = "API key management endpoints"),
+        (name = "two-factor", description = "Two-factor authentication setup endpoints")
     ),
     info(
         title = "Quotes Server API",
@@ -73,19 +365,31 @@ impl Modify for SecurityAddon {
         components.add_security_scheme(
             "bearer_auth",
             SecurityScheme::Http(Http::new(HttpAuthScheme::Bearer)),
+        );
+        components.add_security_scheme(
+            "api_key",
+            SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::new("X-API-Key"))),
         )
     }
 }
 
 /// Get all quotes from the database with optional search filters
 ///
-/// Returns a list of quotes with their associated tags. Can be filtered by quote text, source, or tags.
+/// Returns a page of quotes with their associated tags. Can be filtered by quote text, source, or tags. With a
+/// full-text search (`q`) the best matches come first, each with a highlighted snippet. The total number of
+/// matching quotes is sent in the `X-Total-Count` header, and links to the other pages in the `Link` header.
 #[utoipa::path(
     get,
     path = "/api/v1/quotes",
     params(SearchParams),
     responses(
-        (status = 200, description = "List of quotes successfully retrieved", body = Vec<QuoteWithTags>),
+        (status = 200, description = "List of quotes successfully retrieved", body = Vec<QuoteWithTags>,
+            headers(
+                ("X-Total-Count" = i64, description = "Number of quotes matching the search"),
+                ("Link" = String, description = "URLs of the first, previous, next and last pages")
+            )
+        ),
+        (status = 400, description = "Invalid query parameters"),
         (status = 500, description = "Internal server error")
     ),
     tag = "quotes"
@@ -93,16 +397,42 @@ impl Modify for SecurityAddon {
 pub async fn get_all_quotes(
     State(state): State<AppState>,
     Query(params): Query<SearchParams>,
-) -> Json<Vec<QuoteWithTags>> {
-    let quotes = db::search_quotes(&state.pool, params)
-        .await
-        .expect("Failed to get quotes");
-    Json(quotes)
+) -> Result<(HeaderMap, Json<Vec<QuoteWithTags>>), (StatusCode, String)> {
+    match db::search_quotes(&state.pool, params.clone()).await {
+        Ok(page) => {
+            let offsets = PageOffsets::new(&params, page.total);
+            let link = |offset: i64, rel: &str| {
+                format!(
+                    "</api/v1/quotes?{}>; rel=\"{rel}\"",
+                    params.page_query(offset)
+                )
+            };
+            let mut links = vec![link(0, "first")];
+            links.extend(offsets.prev.map(|offset| link(offset, "prev")));
+            links.extend(offsets.next.map(|offset| link(offset, "next")));
+            links.push(link(offsets.last, "last"));
+
+            let mut headers = HeaderMap::new();
+            headers.insert("x-total-count", page.total.into());
+            if let Ok(value) = links.join(", ").parse() {
+                headers.insert(header::LINK, value);
+            }
+            Ok((headers, Json(page.quotes)))
+        }
+        Err(err) => {
+            eprintln!("Database error: {}", err);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to get quotes".to_string(),
+            ))
+        }
+    }
 }
 
 /// Get a specific quote by ID
 ///
-/// Returns a single quote with its associated tags based on the provided ID.
+/// Returns a single quote with its associated tags based on the provided ID. The `ETag` header carries the
+/// quote's version, to send back in `If-Match` when updating or deleting it.
 #[utoipa::path(
     get,
     path = "/api/v1/quotes/{id}",
@@ -110,7 +440,8 @@ pub async fn get_all_quotes(
         ("id" = i64, Path, description = "Quote database ID")
     ),
     responses(
-        (status = 200, description = "Quote successfully retrieved", body = QuoteWithTags),
+        (status = 200, description = "Quote successfully retrieved", body = QuoteWithTags,
+            headers(("ETag" = String, description = "Version of the quote"))),
         (status = 404, description = "Quote not found"),
         (status = 500, description = "Internal server error")
     ),
@@ -119,9 +450,9 @@ pub async fn get_all_quotes(
 pub async fn get_quote_by_id(
     State(state): State<AppState>,
     Path(id): Path<i64>,
-) -> Result<Json<QuoteWithTags>, (StatusCode, String)> {
+) -> Result<VersionedQuote, (StatusCode, String)> {
     match db::get_quote_by_id(&state.pool, id).await {
-        Ok(Some(quote)) => Ok(Json(quote)),
+        Ok(Some(quote)) => Ok(VersionedQuote(quote)),
         Ok(None) => Err((
             StatusCode::NOT_FOUND,
             format!("Quote with ID {} not found", id),
@@ -155,189 +486,2118 @@ pub async fn get_random_quote(State(state): State<AppState>) -> Json<Option<Quot
     Json(quote)
 }
 
-/// Create a new quote (requires authentication)
+/// Check the fields every quote needs, returning the error message for the first one missing
+pub fn validate_quote(quote: &str, source: &str) -> Result<(), &'static str> {
+    if quote.trim().is_empty() {
+        return Err("Quote text cannot be empty");
+    }
+    if source.trim().is_empty() {
+        return Err("Quote source cannot be empty");
+    }
+    Ok(())
+}
+
+/// Trim a quote's citation, leaving out blank fields, and check it
+pub fn validate_citation(citation: Citation) -> Result<Citation, &'static str> {
+    let text = |field: Option<String>| {
+        field
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty())
+    };
+
+    let url = text(citation.url);
+    if url
+        .as_deref()
+        .is_some_and(|url| !url.starts_with("https://") && !url.starts_with("http://"))
+    {
+        return Err("Citation URL must be an http:// or https:// URL");
+    }
+
+    Ok(Citation {
+        work: text(citation.work),
+        year: citation.year,
+        page: text(citation.page),
+        url,
+        note: text(citation.note),
+    })
+}
+
+/// Create a new quote (requires contributor role)
 ///
-/// Creates a new quote with optional tags and returns the created quote with its assigned ID.
+/// Creates a new quote with an optional citation and tags and returns the created quote with its assigned ID.
 #[utoipa::path(
     post,
     path = "/api/v1/quotes",
     request_body = CreateQuoteRequest,
     responses(
-        (status = 201, description = "Quote successfully created", body = QuoteWithTags),
+        (status = 201, description = "Quote successfully created", body = QuoteWithTags,
+            headers(("ETag" = String, description = "Version of the quote"))),
         (status = 400, description = "Invalid request body"),
         (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Requires contributor role or above"),
         (status = 500, description = "Internal server error")
     ),
     tag = "quotes",
     security(
-        ("bearer_auth" = [])
+        ("bearer_auth" = []),
+        ("api_key" = ["quotes:create"])
     )
 )]
 pub async fn create_quote(
-    _claims: Claims,
+    auth: RequireRole<Contributor>,
     State(state): State<AppState>,
-    Json(request): Json<CreateQuoteRequest>,
-) -> Result<(StatusCode, Json<QuoteWithTags>), (StatusCode, String)> {
+    Json(mut request): Json<CreateQuoteRequest>,
+) -> Result<(StatusCode, VersionedQuote), axum::response::Response> {
+    let user_id = auth.claims.user_id().map_err(IntoResponse::into_response)?;
+
     // Validate input
-    if request.quote.trim().is_empty() {
-        return Err((
-            StatusCode::BAD_REQUEST,
-            "Quote text cannot be empty".to_string(),
-        ));
+    if let Err(message) = validate_quote(&request.quote, &request.source) {
+        return Err((StatusCode::BAD_REQUEST, message.to_string()).into_response());
     }
+    request.citation = validate_citation(request.citation)
+        .map_err(|message| (StatusCode::BAD_REQUEST, message.to_string()).into_response())?;
+    check_author_exists(&state, request.author_id).await?;
 
-    if request.source.trim().is_empty() {
-        return Err((
+    match db::create_quote(&state.pool, request, user_id).await {
+        Ok(quote) => Ok((StatusCode::CREATED, VersionedQuote(quote))),
+        Err(err) => {
+            eprintln!("Database error: {}", err);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to create quote".to_string(),
+            )
+                .into_response())
+        }
+    }
+}
+
+/// Check that the author a quote is linked to by ID, if any, exists
+async fn check_author_exists(
+    state: &AppState,
+    author_id: Option<i64>,
+) -> Result<(), axum::response::Response> {
+    let Some(author_id) = author_id else {
+        return Ok(());
+    };
+
+    match db::get_author(&state.pool, author_id).await {
+        Ok(Some(_)) => Ok(()),
+        Ok(None) => Err((
             StatusCode::BAD_REQUEST,
-            "Quote source cannot be empty".to_string(),
-        ));
+            format!("Author with ID {} not found", author_id),
+        )
+            .into_response()),
+        Err(err) => {
+            eprintln!("Database error: {}", err);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to retrieve author".to_string(),
+            )
+                .into_response())
+        }
     }
+}
+
+/// Check that the request may change the quote with the given ID, see [`Claims::may_modify`].
+/// Returns the user's ID and the quote.
+async fn check_quote_owner(
+    state: &AppState,
+    claims: &Claims,
+    id: i64,
+) -> Result<(i64, QuoteWithTags), axum::response::Response> {
+    if !claims.has_role(Role::Contributor) {
+        return Err(AuthError::Forbidden.into_response());
+    }
+    let user_id = claims.user_id().map_err(IntoResponse::into_response)?;
 
-    match db::create_quote(&state.pool, request).await {
-        Ok(quote) => Ok((StatusCode::CREATED, Json(quote))),
+    match db::get_quote_by_id(&state.pool, id).await {
+        Ok(Some(quote)) if claims.may_modify(quote.created_by) => Ok((user_id, quote)),
+        Ok(Some(_)) => Err(AuthError::Forbidden.into_response()),
+        Ok(None) => Err((
+            StatusCode::NOT_FOUND,
+            format!("Quote with ID {} not found", id),
+        )
+            .into_response()),
         Err(err) => {
             eprintln!("Database error: {}", err);
             Err((
                 StatusCode::INTERNAL_SERVER_ERROR,
-                "Failed to create quote".to_string(),
-            ))
+                "Failed to retrieve quote".to_string(),
+            )
+                .into_response())
         }
     }
 }
 
-/// Update an existing quote (requires authentication)
+/// Entity tag identifying the current version of a quote
+pub fn quote_etag(quote: &QuoteWithTags) -> String {
+    format!("\"{}\"", quote.version)
+}
+
+/// A quote sent with its version in the `ETag` header
+pub struct VersionedQuote(pub QuoteWithTags);
+
+impl IntoResponse for VersionedQuote {
+    fn into_response(self) -> axum::response::Response {
+        ([(header::ETAG, quote_etag(&self.0))], Json(self.0)).into_response()
+    }
+}
+
+/// Check a request's `If-Match` header against the quote it is about to change, refusing it with
+/// 412 Precondition Failed unless one of the listed entity tags is the quote's. Returns the version
+/// the change must still apply to, or `None` when the header is missing or `*`.
+fn check_if_match(
+    headers: &HeaderMap,
+    quote: &QuoteWithTags,
+) -> Result<Option<i64>, axum::response::Response> {
+    let Some(value) = headers.get(header::IF_MATCH) else {
+        return Ok(None);
+    };
+    let value = value.to_str().unwrap_or_default().trim();
+    if value == "*" {
+        return Ok(None);
+    }
+
+    let etag = quote_etag(quote);
+    if value.split(',').any(|tag| tag.trim() == etag) {
+        Ok(Some(quote.version))
+    } else {
+        Err(quote_modified(quote.id))
+    }
+}
+
+/// Response for a change to a quote that was modified since the client read it
+fn quote_modified(id: i64) -> axum::response::Response {
+    (
+        StatusCode::PRECONDITION_FAILED,
+        format!("Quote with ID {} has been modified", id),
+    )
+        .into_response()
+}
+
+/// Update an existing quote (requires editor role, or contributor role for your own quotes)
 ///
-/// Updates an existing quote by ID with new quote text, source, and tags. All existing tags are replaced with the provided ones.
+/// Updates an existing quote by ID with new quote text, source, citation, and tags. The citation and all existing
+/// tags are replaced with the provided ones. With an `If-Match` header, the quote is only updated if it is still
+/// at the version named by the `ETag` the client last received.
 #[utoipa::path(
     put,
     path = "/api/v1/quotes/{id}",
     params(
-        ("id" = i64, Path, description = "Quote database ID to update")
+        ("id" = i64, Path, description = "Quote database ID to update"),
+        ("If-Match" = Option<String>, Header, description = "ETag of the version the update is based on")
     ),
     request_body = UpdateQuoteRequest,
     responses(
-        (status = 200, description = "Quote successfully updated", body = QuoteWithTags),
+        (status = 200, description = "Quote successfully updated", body = QuoteWithTags,
+            headers(("ETag" = String, description = "Version of the quote"))),
         (status = 400, description = "Invalid request body"),
         (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Requires editor role, or contributor role for your own quotes"),
         (status = 404, description = "Quote not found"),
+        (status = 412, description = "Quote has been modified since the version in If-Match"),
         (status = 500, description = "Internal server error")
     ),
     tag = "quotes",
     security(
-        ("bearer_auth" = [])
+        ("bearer_auth" = []),
+        ("api_key" = ["quotes:edit"])
     )
 )]
 pub async fn update_quote(
-    _claims: Claims,
+    claims: Claims,
     State(state): State<AppState>,
     Path(id): Path<i64>,
-    Json(request): Json<UpdateQuoteRequest>,
-) -> Result<Json<QuoteWithTags>, (StatusCode, String)> {
-    // Validate input
-    if request.quote.trim().is_empty() {
-        return Err((
-            StatusCode::BAD_REQUEST,
-            "Quote text cannot be empty".to_string(),
-        ));
-    }
+    headers: HeaderMap,
+    Json(mut request): Json<UpdateQuoteRequest>,
+) -> Result<VersionedQuote, axum::response::Response> {
+    let (user_id, quote) = check_quote_owner(&state, &claims, id).await?;
+    let expected_version = check_if_match(&headers, &quote)?;
 
-    if request.source.trim().is_empty() {
-        return Err((
-            StatusCode::BAD_REQUEST,
-            "Quote source cannot be empty".to_string(),
-        ));
+    // Validate input
+    if let Err(message) = validate_quote(&request.quote, &request.source) {
+        return Err((StatusCode::BAD_REQUEST, message.to_string()).into_response());
     }
+    request.citation = validate_citation(request.citation)
+        .map_err(|message| (StatusCode::BAD_REQUEST, message.to_string()).into_response())?;
+    check_author_exists(&state, request.author_id).await?;
 
-    match db::update_quote(&state.pool, id, request).await {
-        Ok(Some(quote)) => Ok(Json(quote)),
+    match db::update_quote(&state.pool, id, request, user_id, expected_version).await {
+        Ok(Some(quote)) => Ok(VersionedQuote(quote)),
+        Ok(None) if expected_version.is_some() => Err(quote_modified(id)),
         Ok(None) => Err((
             StatusCode::NOT_FOUND,
             format!("Quote with ID {} not found", id),
-        )),
+        )
+            .into_response()),
         Err(err) => {
             eprintln!("Database error: {}", err);
             Err((
                 StatusCode::INTERNAL_SERVER_ERROR,
                 "Failed to update quote".to_string(),
-            ))
+            )
+                .into_response())
+        }
+    }
+}
+
+/// Partially update a quote (requires editor role, or contributor role for your own quotes)
+///
+/// Applies a JSON Merge Patch (RFC 7396) to a quote: fields left out keep their value and `null` clears a citation
+/// field. `tags` replaces the tags when given a list, or adds and removes single tags when given an object such as
+/// `{"inspiration": true, "motivation": null}`. With an `If-Match` header, the quote is only updated if it is still
+/// at the version named by the `ETag` the client last received.
+#[utoipa::path(
+    patch,
+    path = "/api/v1/quotes/{id}",
+    params(
+        ("id" = i64, Path, description = "Quote database ID to update"),
+        ("If-Match" = Option<String>, Header, description = "ETag of the version the patch is based on")
+    ),
+    request_body(content = PatchQuoteRequest, content_type = "application/merge-patch+json"),
+    responses(
+        (status = 200, description = "Quote successfully updated", body = QuoteWithTags,
+            headers(("ETag" = String, description = "Version of the quote"))),
+        (status = 400, description = "Invalid request body"),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Requires editor role, or contributor role for your own quotes"),
+        (status = 404, description = "Quote not found"),
+        (status = 412, description = "Quote has been modified since the version in If-Match, or while it was patched"),
+        (status = 500, description = "Internal server error")
+    ),
+    tag = "quotes",
+    security(
+        ("bearer_auth" = []),
+        ("api_key" = ["quotes:edit"])
+    )
+)]
+pub async fn patch_quote(
+    claims: Claims,
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    headers: HeaderMap,
+    Json(patch): Json<PatchQuoteRequest>,
+) -> Result<VersionedQuote, axum::response::Response> {
+    let (user_id, quote) = check_quote_owner(&state, &claims, id).await?;
+    check_if_match(&headers, &quote)?;
+
+    let mut request = patch.apply_to(&quote);
+    if let Err(message) = validate_quote(&request.quote, &request.source) {
+        return Err((StatusCode::BAD_REQUEST, message.to_string()).into_response());
+    }
+    request.citation = validate_citation(request.citation)
+        .map_err(|message| (StatusCode::BAD_REQUEST, message.to_string()).into_response())?;
+    check_author_exists(&state, request.author_id).await?;
+
+    // The patch was applied to this version, so it must not have changed since
+    match db::update_quote(&state.pool, id, request, user_id, Some(quote.version)).await {
+        Ok(Some(quote)) => Ok(VersionedQuote(quote)),
+        Ok(None) => Err(quote_modified(id)),
+        Err(err) => {
+            eprintln!("Database error: {}", err);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to update quote".to_string(),
+            )
+                .into_response())
         }
     }
 }
 
-/// Delete a quote by ID (requires authentication)
+/// Delete a quote by ID (requires editor role, or contributor role for your own quotes)
 ///
-/// Permanently removes a quote and all its associated tags from the database.
+/// Moves a quote to the trash, where it can be restored or purged until it is purged automatically
+/// after the retention period. With an `If-Match` header, the quote is only deleted if it is still at
+/// the version named by the `ETag` the client last received.
 #[utoipa::path(
     delete,
     path = "/api/v1/quotes/{id}",
     params(
-        ("id" = i64, Path, description = "Quote database ID to delete")
+        ("id" = i64, Path, description = "Quote database ID to delete"),
+        ("If-Match" = Option<String>, Header, description = "ETag of the version the deletion is based on")
     ),
     responses(
-        (status = 204, description = "Quote successfully deleted"),
+        (status = 204, description = "Quote successfully moved to the trash"),
         (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Requires editor role, or contributor role for your own quotes; editors and admins also need two-factor authentication"),
         (status = 404, description = "Quote not found"),
+        (status = 412, description = "Quote has been modified since the version in If-Match"),
         (status = 500, description = "Internal server error")
     ),
     tag = "quotes",
     security(
-        ("bearer_auth" = [])
+        ("bearer_auth" = []),
+        ("api_key" = ["quotes:edit"])
     )
 )]
 pub async fn delete_quote(
-    _claims: Claims,
+    claims: Claims,
     State(state): State<AppState>,
     Path(id): Path<i64>,
-) -> Result<StatusCode, (StatusCode, String)> {
-    match db::delete_quote(&state.pool, id).await {
+    headers: HeaderMap,
+) -> Result<StatusCode, axum::response::Response> {
+    let (user_id, quote) = check_quote_owner(&state, &claims, id).await?;
+    claims
+        .check_second_factor()
+        .map_err(IntoResponse::into_response)?;
+    let expected_version = check_if_match(&headers, &quote)?;
+
+    match db::delete_quote(&state.pool, id, user_id, expected_version).await {
         Ok(true) => Ok(StatusCode::NO_CONTENT),
+        Ok(false) if expected_version.is_some() => Err(quote_modified(id)),
         Ok(false) => Err((
             StatusCode::NOT_FOUND,
             format!("Quote with ID {} not found", id),
-        )),
+        )
+            .into_response()),
         Err(err) => {
             eprintln!("Database error: {}", err);
             Err((
                 StatusCode::INTERNAL_SERVER_ERROR,
                 "Failed to delete quote".to_string(),
-            ))
+            )
+                .into_response())
+        }
+    }
+}
+
+/// List your quotes (requires authentication)
+///
+/// Returns the quotes created by the authenticated user with their associated tags, newest first.
+#[utoipa::path(
+    get,
+    path = "/api/v1/me/quotes",
+    responses(
+        (status = 200, description = "List of quotes successfully retrieved", body = Vec<QuoteWithTags>),
+        (status = 401, description = "Unauthorized"),
+        (status = 500, description = "Internal server error")
+    ),
+    tag = "quotes",
+    security(
+        ("bearer_auth" = []),
+        ("api_key" = [])
+    )
+)]
+pub async fn get_my_quotes(
+    claims: Claims,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<QuoteWithTags>>, axum::response::Response> {
+    let user_id = claims.user_id().map_err(IntoResponse::into_response)?;
+
+    match db::get_quotes_by_creator(&state.pool, user_id).await {
+        Ok(quotes) => Ok(Json(quotes)),
+        Err(err) => {
+            eprintln!("Database error: {}", err);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to retrieve quotes".to_string(),
+            )
+                .into_response())
+        }
+    }
+}
+
+/// List quotes in the trash (requires contributor role)
+///
+/// Returns deleted quotes with their associated tags, most recently deleted first. Editors see every quote in
+/// the trash, contributors only their own.
+#[utoipa::path(
+    get,
+    path = "/api/v1/trash",
+    responses(
+        (status = 200, description = "List of quotes in the trash successfully retrieved", body = Vec<QuoteWithTags>),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Requires contributor role or above"),
+        (status = 500, description = "Internal server error")
+    ),
+    tag = "trash",
+    security(
+        ("bearer_auth" = []),
+        ("api_key" = [])
+    )
+)]
+pub async fn list_trash(
+    claims: Claims,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<QuoteWithTags>>, axum::response::Response> {
+    if !claims.has_role(Role::Contributor) {
+        return Err(AuthError::Forbidden.into_response());
+    }
+    let user_id = claims.user_id().map_err(IntoResponse::into_response)?;
+    let created_by = (!claims.has_role(Role::Editor)).then_some(user_id);
+
+    match db::list_trash(&state.pool, created_by).await {
+        Ok(quotes) => Ok(Json(quotes)),
+        Err(err) => {
+            eprintln!("Database error: {}", err);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to retrieve trash".to_string(),
+            )
+                .into_response())
         }
     }
 }
 
-/// User registration and authentication
+/// Restore a quote from the trash (requires editor role, or contributor role for your own quotes)
 ///
-/// Authenticates a user with their credentials and returns a JWT token for accessing protected endpoints.
+/// Takes a deleted quote back out of the trash with its tags and revision history.
 #[utoipa::path(
     post,
-    path = "/auth",
-    request_body = Registration,
+    path = "/api/v1/trash/{id}/restore",
+    params(
+        ("id" = i64, Path, description = "Database ID of the quote to restore")
+    ),
     responses(
-        (status = 200, description = "User successfully authenticated", body = authjwt::AuthBody),
-        (status = 400, description = "Invalid registration data"),
-        (status = 401, description = "Wrong credentials"),
+        (status = 200, description = "Quote successfully restored", body = QuoteWithTags,
+            headers(("ETag" = String, description = "Version of the quote"))),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Requires editor role, or contributor role for your own quotes"),
+        (status = 404, description = "Quote not found in the trash"),
         (status = 500, description = "Internal server error")
     ),
-    tag = "auth"
+    tag = "trash",
+    security(
+        ("bearer_auth" = []),
+        ("api_key" = ["quotes:edit"])
+    )
 )]
-pub async fn register(
+pub async fn restore_quote(
+    claims: Claims,
     State(state): State<AppState>,
-    Json(registration): Json<Registration>,
-) -> axum::response::Response {
-    match authjwt::make_jwt_token(&state.jwt_keys, &state.reg_key, &registration) {
-        Ok(token) => (StatusCode::OK, Json(token)).into_response(),
-        Err(e) => e.into_response(),
+    Path(id): Path<i64>,
+) -> Result<VersionedQuote, axum::response::Response> {
+    let database_error = |err: sqlx::Error| {
+        eprintln!("Database error: {}", err);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to restore quote".to_string(),
+        )
+            .into_response()
+    };
+    let not_found = || {
+        (
+            StatusCode::NOT_FOUND,
+            format!("Quote with ID {} not found in the trash", id),
+        )
+            .into_response()
+    };
+
+    match db::get_deleted_quote(&state.pool, id)
+        .await
+        .map_err(database_error)?
+    {
+        Some(quote) if claims.may_modify(quote.created_by) => {}
+        Some(_) => return Err(AuthError::Forbidden.into_response()),
+        None => return Err(not_found()),
+    }
+
+    let user_id = claims.user_id().map_err(IntoResponse::into_response)?;
+    if !db::restore_quote(&state.pool, id, user_id)
+        .await
+        .map_err(database_error)?
+    {
+        return Err(not_found());
+    }
+    match db::get_quote_by_id(&state.pool, id)
+        .await
+        .map_err(database_error)?
+    {
+        Some(quote) => Ok(VersionedQuote(quote)),
+        None => Err(not_found()),
     }
 }
 
-/// Create API router with all quote-related endpoints
-pub fn create_api_router() -> utoipa_axum::router::OpenApiRouter<AppState> {
-    utoipa_axum::router::OpenApiRouter::new()
-        .route("/auth", post(register))
-        .route("/api/v1/quotes", get(get_all_quotes).post(create_quote))
-        .route("/api/v1/quotes/random", get(get_random_quote))
-        .route(
-            "/api/v1/quotes/{id}",
-            get(get_quote_by_id).put(update_quote).delete(delete_quote),
+/// Permanently delete a quote in the trash (requires editor role and two-factor authentication)
+///
+/// Removes the quote, its tags and its revision history from the database. This cannot be undone.
+#[utoipa::path(
+    delete,
+    path = "/api/v1/trash/{id}",
+    params(
+        ("id" = i64, Path, description = "Database ID of the quote to purge")
+    ),
+    responses(
+        (status = 204, description = "Quote successfully purged"),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Requires editor role or above with two-factor authentication"),
+        (status = 404, description = "Quote not found in the trash"),
+        (status = 500, description = "Internal server error")
+    ),
+    tag = "trash",
+    security(
+        ("bearer_auth" = []),
+        ("api_key" = ["quotes:edit"])
+    )
+)]
+pub async fn purge_quote(
+    auth: RequireRole<Editor>,
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+) -> Result<StatusCode, axum::response::Response> {
+    auth.claims
+        .check_second_factor()
+        .map_err(IntoResponse::into_response)?;
+
+    match db::purge_quote(&state.pool, id).await {
+        Ok(true) => Ok(StatusCode::NO_CONTENT),
+        Ok(false) => Err((
+            StatusCode::NOT_FOUND,
+            format!("Quote with ID {} not found in the trash", id),
+        )
+            .into_response()),
+        Err(err) => {
+            eprintln!("Database error: {}", err);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to purge quote".to_string(),
+            )
+                .into_response())
+        }
+    }
+}
+
+/// Look up a quote's revisions, failing with 404 if the quote does not exist
+async fn quote_revisions(
+    state: &AppState,
+    id: i64,
+) -> Result<Vec<QuoteRevision>, (StatusCode, String)> {
+    let database_error = |err: sqlx::Error| {
+        eprintln!("Database error: {}", err);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to retrieve revisions".to_string(),
+        )
+    };
+
+    let revisions = db::list_revisions(&state.pool, id)
+        .await
+        .map_err(database_error)?;
+    // Quotes added directly to the database have no revisions yet
+    if revisions.is_empty()
+        && db::get_quote_by_id(&state.pool, id)
+            .await
+            .map_err(database_error)?
+            .is_none()
+    {
+        return Err((
+            StatusCode::NOT_FOUND,
+            format!("Quote with ID {} not found", id),
+        ));
+    }
+    Ok(revisions)
+}
+
+/// List the revisions of a quote
+///
+/// Returns every state the quote has been in, oldest first, with who made each change and when. Each revision
+/// holds the quote as it was after the change; the one before it holds the quote as it was before.
+#[utoipa::path(
+    get,
+    path = "/api/v1/quotes/{id}/revisions",
+    params(
+        ("id" = i64, Path, description = "Quote database ID")
+    ),
+    responses(
+        (status = 200, description = "List of revisions successfully retrieved", body = Vec<QuoteRevision>),
+        (status = 404, description = "Quote not found"),
+        (status = 500, description = "Internal server error")
+    ),
+    tag = "quotes"
+)]
+pub async fn list_revisions(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+) -> Result<Json<Vec<QuoteRevision>>, (StatusCode, String)> {
+    quote_revisions(&state, id).await.map(Json)
+}
+
+/// Get a specific revision of a quote
+///
+/// Returns the quote as it was after the change that produced the revision.
+#[utoipa::path(
+    get,
+    path = "/api/v1/quotes/{id}/revisions/{revision}",
+    params(
+        ("id" = i64, Path, description = "Quote database ID"),
+        ("revision" = i64, Path, description = "Revision number")
+    ),
+    responses(
+        (status = 200, description = "Revision successfully retrieved", body = QuoteRevision),
+        (status = 404, description = "Quote or revision not found"),
+        (status = 500, description = "Internal server error")
+    ),
+    tag = "quotes"
+)]
+pub async fn get_revision(
+    State(state): State<AppState>,
+    Path((id, revision)): Path<(i64, i64)>,
+) -> Result<Json<QuoteRevision>, (StatusCode, String)> {
+    match db::get_revision(&state.pool, id, revision).await {
+        Ok(Some(revision)) => Ok(Json(revision)),
+        Ok(None) => Err((
+            StatusCode::NOT_FOUND,
+            format!("Revision {} of quote {} not found", revision, id),
+        )),
+        Err(err) => {
+            eprintln!("Database error: {}", err);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to retrieve revision".to_string(),
+            ))
+        }
+    }
+}
+
+/// Compare two revisions of a quote
+///
+/// Returns the fields that differ between the revisions, with their values before and after, and the tags added
+/// and removed. By default the latest revision is compared with the one before it.
+#[utoipa::path(
+    get,
+    path = "/api/v1/quotes/{id}/diff",
+    params(
+        ("id" = i64, Path, description = "Quote database ID"),
+        DiffParams
+    ),
+    responses(
+        (status = 200, description = "Revisions successfully compared", body = RevisionDiff),
+        (status = 400, description = "No earlier revision to compare with"),
+        (status = 404, description = "Quote or revision not found"),
+        (status = 500, description = "Internal server error")
+    ),
+    tag = "quotes"
+)]
+pub async fn diff_revisions(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    Query(params): Query<DiffParams>,
+) -> Result<Json<RevisionDiff>, (StatusCode, String)> {
+    let revisions = quote_revisions(&state, id).await?;
+    let find = |number: i64| {
+        revisions
+            .iter()
+            .find(|revision| revision.revision == number)
+            .ok_or_else(|| {
+                (
+                    StatusCode::NOT_FOUND,
+                    format!("Revision {} of quote {} not found", number, id),
+                )
+            })
+    };
+
+    let to = match params.to {
+        Some(to) => find(to)?,
+        None => revisions.last().ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                format!("Quote {} has no revisions", id),
+            )
+        })?,
+    };
+    let from = match params.from {
+        Some(from) => find(from)?,
+        None if to.revision > 1 => find(to.revision - 1)?,
+        None => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!(
+                    "Revision {} has no earlier revision to compare with",
+                    to.revision
+                ),
+            ));
+        }
+    };
+    Ok(Json(from.diff(to)))
+}
+
+/// Restore a revision of a quote (requires editor role, or contributor role for your own quotes)
+///
+/// Puts the quote's text, source, author, citation and tags back the way the revision left them. The restore
+/// is recorded as a new revision, so it can be undone the same way. With an `If-Match` header, the revision is only
+/// restored if the quote is still at the version named by the `ETag` the client last received.
+#[utoipa::path(
+    post,
+    path = "/api/v1/quotes/{id}/revisions/{revision}/restore",
+    params(
+        ("id" = i64, Path, description = "Quote database ID"),
+        ("revision" = i64, Path, description = "Revision number to restore"),
+        ("If-Match" = Option<String>, Header, description = "ETag of the version the restore is based on")
+    ),
+    responses(
+        (status = 200, description = "Revision successfully restored", body = QuoteWithTags,
+            headers(("ETag" = String, description = "Version of the quote"))),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Requires editor role, or contributor role for your own quotes"),
+        (status = 404, description = "Quote or revision not found"),
+        (status = 412, description = "Quote has been modified since the version in If-Match"),
+        (status = 500, description = "Internal server error")
+    ),
+    tag = "quotes",
+    security(
+        ("bearer_auth" = []),
+        ("api_key" = ["quotes:edit"])
+    )
+)]
+pub async fn restore_revision(
+    claims: Claims,
+    State(state): State<AppState>,
+    Path((id, revision)): Path<(i64, i64)>,
+    headers: HeaderMap,
+) -> Result<VersionedQuote, axum::response::Response> {
+    let (user_id, quote) = check_quote_owner(&state, &claims, id).await?;
+    let expected_version = check_if_match(&headers, &quote)?;
+    let revision_not_found = || {
+        (
+            StatusCode::NOT_FOUND,
+            format!("Revision {} of quote {} not found", revision, id),
+        )
+            .into_response()
+    };
+
+    // Tell a missing revision apart from a quote changed since the If-Match version
+    match db::get_revision(&state.pool, id, revision).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return Err(revision_not_found()),
+        Err(err) => {
+            eprintln!("Database error: {}", err);
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to restore revision".to_string(),
+            )
+                .into_response());
+        }
+    }
+
+    match db::restore_revision(&state.pool, id, revision, user_id, expected_version).await {
+        Ok(Some(quote)) => Ok(VersionedQuote(quote)),
+        Ok(None) if expected_version.is_some() => Err(quote_modified(id)),
+        Ok(None) => Err(revision_not_found()),
+        Err(err) => {
+            eprintln!("Database error: {}", err);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to restore revision".to_string(),
+            )
+                .into_response())
+        }
+    }
+}
+
+/// List all tags
+///
+/// Returns every tag in alphabetical order with the number of quotes carrying it.
+#[utoipa::path(
+    get,
+    path = "/api/v1/tags",
+    responses(
+        (status = 200, description = "List of tags successfully retrieved", body = Vec<TagWithCount>),
+        (status = 500, description = "Internal server error")
+    ),
+    tag = "tags"
+)]
+pub async fn list_tags(
+    State(state): State<AppState>,
+) -> Result<Json<Vec<TagWithCount>>, (StatusCode, String)> {
+    match db::list_tags(&state.pool).await {
+        Ok(tags) => Ok(Json(tags)),
+        Err(err) => {
+            eprintln!("Database error: {}", err);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to retrieve tags".to_string(),
+            ))
+        }
+    }
+}
+
+/// Rename a tag (requires editor role)
+///
+/// Renames the tag on every quote carrying it. To combine it with a tag that already has the new name,
+/// merge the two instead.
+#[utoipa::path(
+    put,
+    path = "/api/v1/tags/{id}",
+    params(
+        ("id" = i64, Path, description = "Tag database ID to rename")
+    ),
+    request_body = RenameTagRequest,
+    responses(
+        (status = 200, description = "Tag successfully renamed", body = TagWithCount),
+        (status = 400, description = "Invalid request body"),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Requires editor role"),
+        (status = 404, description = "Tag not found"),
+        (status = 409, description = "Another tag already has the name"),
+        (status = 500, description = "Internal server error")
+    ),
+    tag = "tags",
+    security(
+        ("bearer_auth" = []),
+        ("api_key" = ["quotes:edit"])
+    )
+)]
+pub async fn rename_tag(
+    auth: RequireRole<Editor>,
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    Json(request): Json<RenameTagRequest>,
+) -> Result<Json<TagWithCount>, axum::response::Response> {
+    let user_id = auth.claims.user_id().map_err(IntoResponse::into_response)?;
+    let name = request.name.trim();
+    if name.is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "Tag name cannot be empty".to_string(),
+        )
+            .into_response());
+    }
+
+    match db::rename_tag(&state.pool, id, name, user_id).await {
+        Ok(Some(tag)) => Ok(Json(tag)),
+        Ok(None) => Err((
+            StatusCode::NOT_FOUND,
+            format!("Tag with ID {} not found", id),
+        )
+            .into_response()),
+        Err(sqlx::Error::Database(err)) if err.is_unique_violation() => Err((
+            StatusCode::CONFLICT,
+            format!("Tag \"{}\" already exists; merge the tags instead", name),
+        )
+            .into_response()),
+        Err(err) => {
+            eprintln!("Database error: {}", err);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to rename tag".to_string(),
+            )
+                .into_response())
+        }
+    }
+}
+
+/// Merge a tag into another (requires editor role)
+///
+/// Moves every quote carrying the tag to the `into` tag, then deletes the tag. Quotes carrying both keep the
+/// `into` tag once. Returns the remaining tag.
+#[utoipa::path(
+    post,
+    path = "/api/v1/tags/{id}/merge",
+    params(
+        ("id" = i64, Path, description = "Tag database ID to merge and delete")
+    ),
+    request_body = MergeTagRequest,
+    responses(
+        (status = 200, description = "Tags successfully merged", body = TagWithCount),
+        (status = 400, description = "Invalid request body"),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Requires editor role"),
+        (status = 404, description = "Tag not found"),
+        (status = 500, description = "Internal server error")
+    ),
+    tag = "tags",
+    security(
+        ("bearer_auth" = []),
+        ("api_key" = ["quotes:edit"])
+    )
+)]
+pub async fn merge_tag(
+    auth: RequireRole<Editor>,
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    Json(request): Json<MergeTagRequest>,
+) -> Result<Json<TagWithCount>, axum::response::Response> {
+    let user_id = auth.claims.user_id().map_err(IntoResponse::into_response)?;
+    if request.into == id {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "Cannot merge a tag into itself".to_string(),
+        )
+            .into_response());
+    }
+
+    match db::merge_tags(&state.pool, id, request.into, user_id).await {
+        Ok(Some(tag)) => Ok(Json(tag)),
+        Ok(None) => Err((StatusCode::NOT_FOUND, "Tag not found".to_string()).into_response()),
+        Err(err) => {
+            eprintln!("Database error: {}", err);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to merge tags".to_string(),
+            )
+                .into_response())
+        }
+    }
+}
+
+/// Trim an author's details and check them
+pub fn validate_author(request: AuthorRequest) -> Result<AuthorRequest, &'static str> {
+    let name = request.name.trim().to_string();
+    if name.is_empty() {
+        return Err("Author name cannot be empty");
+    }
+    if let (Some(birth), Some(death)) = (request.birth_year, request.death_year)
+        && birth > death
+    {
+        return Err("Birth year cannot be after death year");
+    }
+
+    let links: Vec<String> = request
+        .links
+        .iter()
+        .map(|link| link.trim().to_string())
+        .filter(|link| !link.is_empty())
+        .collect();
+    if !links
+        .iter()
+        .all(|link| link.starts_with("https://") || link.starts_with("http://"))
+    {
+        return Err("Author links must be http:// or https:// URLs");
+    }
+
+    Ok(AuthorRequest {
+        name,
+        aliases: request.aliases,
+        birth_year: request.birth_year,
+        death_year: request.death_year,
+        bio: request
+            .bio
+            .map(|bio| bio.trim().to_string())
+            .filter(|bio| !bio.is_empty()),
+        links,
+    })
+}
+
+/// Check that an author's name and aliases do not belong to another author
+async fn check_author_names(
+    state: &AppState,
+    request: &AuthorRequest,
+    author_id: Option<i64>,
+) -> Result<(), axum::response::Response> {
+    for name in std::iter::once(&request.name).chain(&request.aliases) {
+        match db::author_name_taken(&state.pool, name.trim(), author_id).await {
+            Ok(false) => {}
+            Ok(true) => {
+                return Err((
+                    StatusCode::CONFLICT,
+                    format!("\"{}\" already names another author", name.trim()),
+                )
+                    .into_response());
+            }
+            Err(err) => {
+                eprintln!("Database error: {}", err);
+                return Err((
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Failed to check author names".to_string(),
+                )
+                    .into_response());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// List all authors
+///
+/// Returns every author in alphabetical order with their aliases and the number of quotes linked to them.
+#[utoipa::path(
+    get,
+    path = "/api/v1/authors",
+    responses(
+        (status = 200, description = "List of authors successfully retrieved", body = Vec<Author>),
+        (status = 500, description = "Internal server error")
+    ),
+    tag = "authors"
+)]
+pub async fn list_authors(
+    State(state): State<AppState>,
+) -> Result<Json<Vec<Author>>, (StatusCode, String)> {
+    match db::list_authors(&state.pool).await {
+        Ok(authors) => Ok(Json(authors)),
+        Err(err) => {
+            eprintln!("Database error: {}", err);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to retrieve authors".to_string(),
+            ))
+        }
+    }
+}
+
+/// Get a specific author by ID
+///
+/// Their quotes are listed by `GET /api/v1/quotes?author_id={id}`.
+#[utoipa::path(
+    get,
+    path = "/api/v1/authors/{id}",
+    params(
+        ("id" = i64, Path, description = "Author database ID")
+    ),
+    responses(
+        (status = 200, description = "Author successfully retrieved", body = Author),
+        (status = 404, description = "Author not found"),
+        (status = 500, description = "Internal server error")
+    ),
+    tag = "authors"
+)]
+pub async fn get_author(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+) -> Result<Json<Author>, (StatusCode, String)> {
+    match db::get_author(&state.pool, id).await {
+        Ok(Some(author)) => Ok(Json(author)),
+        Ok(None) => Err((
+            StatusCode::NOT_FOUND,
+            format!("Author with ID {} not found", id),
+        )),
+        Err(err) => {
+            eprintln!("Database error: {}", err);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to retrieve author".to_string(),
+            ))
+        }
+    }
+}
+
+/// Create an author (requires editor role)
+///
+/// Quote sources matching the author's name or one of the aliases are linked to the author from then on.
+#[utoipa::path(
+    post,
+    path = "/api/v1/authors",
+    request_body = AuthorRequest,
+    responses(
+        (status = 201, description = "Author successfully created", body = Author),
+        (status = 400, description = "Invalid request body"),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Requires editor role"),
+        (status = 409, description = "The name or an alias already names another author"),
+        (status = 500, description = "Internal server error")
+    ),
+    tag = "authors",
+    security(
+        ("bearer_auth" = []),
+        ("api_key" = ["quotes:edit"])
+    )
+)]
+pub async fn create_author(
+    _auth: RequireRole<Editor>,
+    State(state): State<AppState>,
+    Json(request): Json<AuthorRequest>,
+) -> Result<(StatusCode, Json<Author>), axum::response::Response> {
+    let request = validate_author(request)
+        .map_err(|message| (StatusCode::BAD_REQUEST, message.to_string()).into_response())?;
+    check_author_names(&state, &request, None).await?;
+
+    match db::create_author(&state.pool, &request).await {
+        Ok(author) => Ok((StatusCode::CREATED, Json(author))),
+        Err(err) => {
+            eprintln!("Database error: {}", err);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to create author".to_string(),
+            )
+                .into_response())
+        }
+    }
+}
+
+/// Update an author (requires editor role)
+///
+/// Replaces the author's details; the aliases and links given replace the existing ones.
+#[utoipa::path(
+    put,
+    path = "/api/v1/authors/{id}",
+    params(
+        ("id" = i64, Path, description = "Author database ID to update")
+    ),
+    request_body = AuthorRequest,
+    responses(
+        (status = 200, description = "Author successfully updated", body = Author),
+        (status = 400, description = "Invalid request body"),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Requires editor role"),
+        (status = 404, description = "Author not found"),
+        (status = 409, description = "The name or an alias already names another author"),
+        (status = 500, description = "Internal server error")
+    ),
+    tag = "authors",
+    security(
+        ("bearer_auth" = []),
+        ("api_key" = ["quotes:edit"])
+    )
+)]
+pub async fn update_author(
+    _auth: RequireRole<Editor>,
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    Json(request): Json<AuthorRequest>,
+) -> Result<Json<Author>, axum::response::Response> {
+    let request = validate_author(request)
+        .map_err(|message| (StatusCode::BAD_REQUEST, message.to_string()).into_response())?;
+    check_author_names(&state, &request, Some(id)).await?;
+
+    match db::update_author(&state.pool, id, &request).await {
+        Ok(Some(author)) => Ok(Json(author)),
+        Ok(None) => Err((
+            StatusCode::NOT_FOUND,
+            format!("Author with ID {} not found", id),
+        )
+            .into_response()),
+        Err(err) => {
+            eprintln!("Database error: {}", err);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to update author".to_string(),
+            )
+                .into_response())
+        }
+    }
+}
+
+/// Delete an author (requires editor role)
+///
+/// Only authors without quotes, including quotes in the trash, can be deleted; link their quotes to another
+/// author or purge them first.
+#[utoipa::path(
+    delete,
+    path = "/api/v1/authors/{id}",
+    params(
+        ("id" = i64, Path, description = "Author database ID to delete")
+    ),
+    responses(
+        (status = 204, description = "Author successfully deleted"),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Requires editor role"),
+        (status = 404, description = "Author not found"),
+        (status = 409, description = "The author still has quotes, possibly in the trash"),
+        (status = 500, description = "Internal server error")
+    ),
+    tag = "authors",
+    security(
+        ("bearer_auth" = []),
+        ("api_key" = ["quotes:edit"])
+    )
+)]
+pub async fn delete_author(
+    _auth: RequireRole<Editor>,
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let database_error = |err: sqlx::Error| {
+        eprintln!("Database error: {}", err);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to delete author".to_string(),
+        )
+    };
+
+    match db::get_author(&state.pool, id)
+        .await
+        .map_err(database_error)?
+    {
+        Some(_) => {
+            // Quotes in the trash count too, or restoring them would bring the author back
+            if db::delete_author(&state.pool, id)
+                .await
+                .map_err(database_error)?
+            {
+                Ok(StatusCode::NO_CONTENT)
+            } else {
+                Err((
+                    StatusCode::CONFLICT,
+                    format!("Author with ID {} still has quotes", id),
+                ))
+            }
+        }
+        None => Err((
+            StatusCode::NOT_FOUND,
+            format!("Author with ID {} not found", id),
+        )),
+    }
+}
+
+/// User login
+///
+/// Authenticates a user with their email and password and returns a short-lived JWT access token
+/// for protected endpoints, plus a refresh token for obtaining new access tokens. Accounts with two-factor
+/// authentication enabled instead receive a challenge to complete at `/auth/totp`.
+#[utoipa::path(
+    post,
+    path = "/auth",
+    request_body = Credentials,
+    responses(
+        (status = 200, description = "User successfully authenticated", body = authjwt::AuthBody),
+        (status = 202, description = "Password accepted, a TOTP code is required", body = TotpChallenge),
+        (status = 400, description = "Missing credentials"),
+        (status = 401, description = "Wrong credentials"),
+        (status = 403, description = "Account disabled"),
+        (status = 429, description = "Too many failed attempts; see the Retry-After header"),
+        (status = 500, description = "Internal server error")
+    ),
+    tag = "auth"
+)]
+pub async fn login(
+    State(state): State<AppState>,
+    ClientIp(ip): ClientIp,
+    Json(credentials): Json<Credentials>,
+) -> axum::response::Response {
+    let user = match throttle::authenticate_password(
+        &state.pool,
+        &state.throttle,
+        throttle::API_LOGIN,
+        &ip,
+        &credentials.email,
+        credentials.password,
+    )
+    .await
+    {
+        Ok(user) => user,
+        Err(e) => return e.into_response(),
+    };
+
+    if user.totp_enabled() {
+        return match totp::start_challenge(&state.pool, &user).await {
+            Ok(challenge) => (StatusCode::ACCEPTED, Json(challenge)).into_response(),
+            Err(e) => e.into_response(),
+        };
+    }
+
+    match authjwt::start_session(&state.pool, &state.jwt_keys, &user, false).await {
+        Ok(token) => (StatusCode::OK, Json(token)).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+/// Complete a two-step login
+///
+/// Exchanges the challenge returned by `POST /auth` (or the OpenID Connect callback) and a code from the
+/// authenticator app for tokens. A recovery code can be used once in place of the app's code.
+#[utoipa::path(
+    post,
+    path = "/auth/totp",
+    request_body = TotpLoginRequest,
+    responses(
+        (status = 200, description = "User successfully authenticated", body = authjwt::AuthBody),
+        (status = 400, description = "Unknown or already completed challenge"),
+        (status = 401, description = "Wrong code or expired challenge"),
+        (status = 403, description = "Account disabled"),
+        (status = 429, description = "Too many failed attempts; see the Retry-After header"),
+        (status = 500, description = "Internal server error")
+    ),
+    tag = "auth"
+)]
+pub async fn login_totp(
+    State(state): State<AppState>,
+    ClientIp(ip): ClientIp,
+    Json(request): Json<TotpLoginRequest>,
+) -> axum::response::Response {
+    let user = match throttle::complete_challenge(
+        &state.pool,
+        &state.throttle,
+        throttle::API_TOTP,
+        &ip,
+        &request.challenge,
+        &request.code,
+    )
+    .await
+    {
+        Ok(user) => user,
+        Err(e) => return e.into_response(),
+    };
+
+    match authjwt::start_session(&state.pool, &state.jwt_keys, &user, true).await {
+        Ok(token) => (StatusCode::OK, Json(token)).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+/// Refresh an access token
+///
+/// Exchanges a refresh token for a new access token. The refresh token is single-use; the response contains its replacement.
+#[utoipa::path(
+    post,
+    path = "/auth/refresh",
+    request_body = RefreshRequest,
+    responses(
+        (status = 200, description = "Tokens successfully refreshed", body = authjwt::AuthBody),
+        (status = 400, description = "Unknown or already used refresh token"),
+        (status = 401, description = "Session expired or revoked"),
+        (status = 403, description = "Account disabled"),
+        (status = 500, description = "Internal server error")
+    ),
+    tag = "auth"
+)]
+pub async fn refresh(
+    State(state): State<AppState>,
+    Json(request): Json<RefreshRequest>,
+) -> axum::response::Response {
+    match authjwt::refresh_session(&state.pool, &state.jwt_keys, &request.refresh_token).await {
+        Ok(token) => (StatusCode::OK, Json(token)).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+/// Log out
+///
+/// Revokes the current session: the access token used for this request and the session's refresh token stop working immediately.
+#[utoipa::path(
+    post,
+    path = "/auth/logout",
+    responses(
+        (status = 204, description = "Session successfully revoked"),
+        (status = 400, description = "Request was authenticated with an API key"),
+        (status = 401, description = "Unauthorized"),
+        (status = 500, description = "Internal server error")
+    ),
+    tag = "auth",
+    security(
+        ("bearer_auth" = [])
+    )
+)]
+pub async fn logout(claims: Claims, State(state): State<AppState>) -> axum::response::Response {
+    if claims.api_key_id.is_some() {
+        return (
+            StatusCode::BAD_REQUEST,
+            "API keys are revoked with DELETE /api/v1/api-keys/{id}".to_string(),
+        )
+            .into_response();
+    }
+
+    match authjwt::end_session(&state.pool, &claims).await {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+/// User registration
+///
+/// Creates a new user account. Requires the registration key issued by the server operator.
+/// The first account becomes an admin; later accounts start as contributors.
+#[utoipa::path(
+    post,
+    path = "/auth/register",
+    request_body = Registration,
+    responses(
+        (status = 201, description = "User successfully registered", body = UserProfile),
+        (status = 400, description = "Invalid registration data"),
+        (status = 401, description = "Wrong registration key"),
+        (status = 409, description = "Email already registered"),
+        (status = 429, description = "Too many failed attempts; see the Retry-After header"),
+        (status = 500, description = "Internal server error")
+    ),
+    tag = "auth"
+)]
+pub async fn register(
+    State(state): State<AppState>,
+    ClientIp(ip): ClientIp,
+    Json(registration): Json<Registration>,
+) -> axum::response::Response {
+    if let Err(e) = throttle::check(&state.pool, &state.throttle, &ip, None).await {
+        return e.into_response();
+    }
+    if registration.reg_key != state.reg_key {
+        throttle::record(
+            &state.pool,
+            throttle::REGISTER,
+            &ip,
+            None,
+            Some("wrong registration key"),
+        )
+        .await;
+        return AuthError::WrongCredentials.into_response();
+    }
+
+    let full_name = registration.full_name.trim();
+    let email = registration.email.trim().to_lowercase();
+    if full_name.is_empty() || !email.contains('@') {
+        return (
+            StatusCode::BAD_REQUEST,
+            "A full name and a valid email are required".to_string(),
+        )
+            .into_response();
+    }
+
+    if registration.password.chars().count() < 8 {
+        return (
+            StatusCode::BAD_REQUEST,
+            "Password must be at least 8 characters".to_string(),
+        )
+            .into_response();
+    }
+
+    let password_hash = match authjwt::hash_password(registration.password).await {
+        Ok(hash) => hash,
+        Err(e) => return e.into_response(),
+    };
+
+    match db::create_user(
+        &state.pool,
+        full_name,
+        &email,
+        &password_hash,
+        Role::Contributor,
+    )
+    .await
+    {
+        Ok(user) => (StatusCode::CREATED, Json(UserProfile::from(user))).into_response(),
+        Err(sqlx::Error::Database(err)) if err.is_unique_violation() => {
+            AuthError::EmailTaken.into_response()
+        }
+        Err(err) => {
+            eprintln!("Database error: {}", err);
+            AuthError::Database.into_response()
+        }
+    }
+}
+
+/// Start an OpenID Connect login
+///
+/// Redirects the browser to the identity provider. After signing in there, the provider sends the browser back to
+/// `/auth/oidc/callback`. With `return_to`, the login signs in to the web UI instead of returning tokens.
+#[utoipa::path(
+    get,
+    path = "/auth/oidc/login",
+    params(OidcLoginParams),
+    responses(
+        (status = 303, description = "Redirect to the identity provider"),
+        (status = 404, description = "OpenID Connect login is not configured"),
+        (status = 502, description = "Identity provider unavailable")
+    ),
+    tag = "auth"
+)]
+pub async fn oidc_login(
+    State(state): State<AppState>,
+    Query(params): Query<OidcLoginParams>,
+) -> axum::response::Response {
+    let Some(oidc) = &state.oidc else {
+        return OidcError::NotConfigured.into_response();
+    };
+
+    let return_to = params.return_to.map(|path| local_path(&path).to_string());
+    match oidc.authorization_url(return_to).await {
+        Ok((url, login_state)) => {
+            let secure = if state.secure_cookies || oidc.secure_cookies() {
+                "; Secure"
+            } else {
+                ""
+            };
+            let cookie = format!(
+                "{}={}; Path=/auth/oidc; HttpOnly; SameSite=Lax; Max-Age=600{}",
+                oidc::STATE_COOKIE,
+                login_state,
+                secure
+            );
+            ([(header::SET_COOKIE, cookie)], Redirect::to(&url)).into_response()
+        }
+        Err(e) => e.into_response(),
+    }
+}
+
+/// Finish an OpenID Connect login
+///
+/// Called by the identity provider's redirect. Verifies the returned ID token, maps the identity to a local user
+/// (linking by verified email or creating an account on first login) and returns the same tokens as `POST /auth`.
+/// Web UI logins instead receive a session cookie and are redirected back to the page they started from. Accounts with
+/// two-factor authentication enabled get a challenge, or are sent to the web UI's code page, as with a password login.
+#[utoipa::path(
+    get,
+    path = "/auth/oidc/callback",
+    params(OidcCallback),
+    responses(
+        (status = 200, description = "User successfully authenticated", body = authjwt::AuthBody),
+        (status = 202, description = "Identity verified, a TOTP code is required", body = TotpChallenge),
+        (status = 303, description = "Web UI login successful, redirect to the web UI"),
+        (status = 400, description = "Login was cancelled, expired or started in another browser"),
+        (status = 401, description = "Invalid ID token"),
+        (status = 403, description = "Account disabled or no verified email address"),
+        (status = 404, description = "OpenID Connect login is not configured"),
+        (status = 502, description = "Identity provider unavailable")
+    ),
+    tag = "auth"
+)]
+pub async fn oidc_callback(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(callback): Query<OidcCallback>,
+) -> axum::response::Response {
+    let Some(oidc) = &state.oidc else {
+        return OidcError::NotConfigured.into_response();
+    };
+
+    if let Some(error) = callback.error {
+        return (
+            StatusCode::BAD_REQUEST,
+            format!("Identity provider returned error: {}", error),
+        )
+            .into_response();
+    }
+
+    let (Some(code), Some(login_state)) = (callback.code, callback.state) else {
+        return AuthError::MissingCredentials.into_response();
+    };
+
+    // The state must come back to the browser that started the login
+    if authjwt::cookie(&headers, oidc::STATE_COOKIE) != Some(login_state.as_str()) {
+        return OidcError::InvalidState.into_response();
+    }
+
+    let login = match oidc.exchange(&code, &login_state).await {
+        Ok(login) => login,
+        Err(e) => return e.into_response(),
+    };
+
+    let user = match oidc::resolve_user(&state.pool, oidc.issuer(), &login.claims).await {
+        Ok(user) => user,
+        Err(e) => return e.into_response(),
+    };
+
+    let clear_cookie = format!("{}=; Path=/auth/oidc; Max-Age=0", oidc::STATE_COOKIE);
+    if user.totp_enabled() {
+        let challenge = match totp::start_challenge(&state.pool, &user).await {
+            Ok(challenge) => challenge,
+            Err(e) => return e.into_response(),
+        };
+        return match login.return_to {
+            Some(return_to) => (
+                [(header::SET_COOKIE, clear_cookie)],
+                Redirect::to(&format!(
+                    "/login/totp?challenge={}&next={}",
+                    challenge.challenge,
+                    totp::percent_encode(&return_to)
+                )),
+            )
+                .into_response(),
+            None => (
+                StatusCode::ACCEPTED,
+                [(header::SET_COOKIE, clear_cookie)],
+                Json(challenge),
+            )
+                .into_response(),
+        };
+    }
+
+    if let Some(return_to) = login.return_to {
+        return match authjwt::start_web_session(&state.pool, &user, false, state.secure_cookies)
+            .await
+        {
+            Ok(session_cookie) => (
+                AppendHeaders([
+                    (header::SET_COOKIE, clear_cookie),
+                    (header::SET_COOKIE, session_cookie),
+                ]),
+                Redirect::to(&return_to),
+            )
+                .into_response(),
+            Err(e) => e.into_response(),
+        };
+    }
+
+    match authjwt::start_session(&state.pool, &state.jwt_keys, &user, false).await {
+        Ok(token) => (
+            StatusCode::OK,
+            [(header::SET_COOKIE, clear_cookie)],
+            Json(token),
+        )
+            .into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+/// Public signing keys
+///
+/// Returns the JWK set other services can use to verify tokens offline. Empty when tokens are signed with a shared secret.
+#[utoipa::path(
+    get,
+    path = "/.well-known/jwks.json",
+    responses(
+        (status = 200, description = "JWK set successfully retrieved", body = Object)
+    ),
+    tag = "auth"
+)]
+pub async fn jwks(State(state): State<AppState>) -> Json<jsonwebtoken::jwk::JwkSet> {
+    Json(state.jwt_keys.jwks())
+}
+
+/// Reload the signing key ring (requires admin role)
+///
+/// Re-reads the configured key files. New tokens are signed with the newest key; keys whose files
+/// were removed from `JWT_KEYS_DIR` are retired and tokens signed with them stop validating. A changed
+/// `JWT_SECRET` or PEM key pair replaces the signing key, and the previous key keeps validating its
+/// tokens until the next change.
+#[utoipa::path(
+    post,
+    path = "/api/v1/keys/reload",
+    responses(
+        (status = 200, description = "Key ring successfully reloaded", body = KeyRingStatus),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Requires admin role"),
+        (status = 500, description = "Key files could not be loaded; the previous key ring stays active")
+    ),
+    tag = "auth",
+    security(
+        ("bearer_auth" = []),
+        ("api_key" = ["admin"])
+    )
+)]
+pub async fn reload_keys(
+    _auth: RequireRole<Admin>,
+    State(state): State<AppState>,
+) -> Result<Json<KeyRingStatus>, (StatusCode, String)> {
+    if let Err(err) = state.jwt_keys.reload().await {
+        let message = format!("Failed to reload signing keys: {}", err);
+        eprintln!("{}", message);
+        return Err((StatusCode::INTERNAL_SERVER_ERROR, message));
+    }
+
+    Ok(Json(KeyRingStatus::from(&state.jwt_keys)))
+}
+
+/// List your API keys (requires authentication)
+///
+/// Returns the API keys belonging to the authenticated user. Key values are never returned again after creation.
+#[utoipa::path(
+    get,
+    path = "/api/v1/api-keys",
+    responses(
+        (status = 200, description = "List of API keys successfully retrieved", body = Vec<ApiKeyInfo>),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "API keys cannot manage API keys"),
+        (status = 500, description = "Internal server error")
+    ),
+    tag = "api-keys",
+    security(
+        ("bearer_auth" = [])
+    )
+)]
+pub async fn list_api_keys(
+    claims: Claims,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<ApiKeyInfo>>, axum::response::Response> {
+    if claims.api_key_id.is_some() {
+        return Err(AuthError::Forbidden.into_response());
+    }
+    let user_id = claims.user_id().map_err(IntoResponse::into_response)?;
+
+    match db::list_api_keys(&state.pool, user_id).await {
+        Ok(keys) => Ok(Json(keys.into_iter().map(ApiKeyInfo::from).collect())),
+        Err(err) => {
+            eprintln!("Database error: {}", err);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to retrieve API keys".to_string(),
+            )
+                .into_response())
+        }
+    }
+}
+
+/// Create an API key (requires authentication)
+///
+/// Creates a long-lived key to send in the `X-API-Key` header instead of a bearer token. The key is only shown in this
+/// response. Scopes cannot exceed the role of the user creating the key.
+#[utoipa::path(
+    post,
+    path = "/api/v1/api-keys",
+    request_body = CreateApiKeyRequest,
+    responses(
+        (status = 201, description = "API key successfully created", body = CreatedApiKey),
+        (status = 400, description = "Invalid request body"),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Requested scope exceeds your role, or request used an API key"),
+        (status = 500, description = "Internal server error")
+    ),
+    tag = "api-keys",
+    security(
+        ("bearer_auth" = [])
+    )
+)]
+pub async fn create_api_key(
+    claims: Claims,
+    State(state): State<AppState>,
+    Json(request): Json<CreateApiKeyRequest>,
+) -> Result<(StatusCode, Json<CreatedApiKey>), axum::response::Response> {
+    if claims.api_key_id.is_some() {
+        return Err(AuthError::Forbidden.into_response());
+    }
+    let user_id = claims.user_id().map_err(IntoResponse::into_response)?;
+
+    // Validate input
+    if request.name.trim().is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "API key name cannot be empty".to_string(),
+        )
+            .into_response());
+    }
+
+    if request.scopes.is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "API key needs at least one scope".to_string(),
+        )
+            .into_response());
+    }
+
+    if request
+        .scopes
+        .iter()
+        .any(|scope| !claims.has_role(scope.role()))
+    {
+        return Err(AuthError::Forbidden.into_response());
+    }
+
+    let (key, prefix, key_hash) = authjwt::make_api_key();
+    match db::create_api_key(&state.pool, user_id, &prefix, &key_hash, request).await {
+        Ok(api_key) => Ok((
+            StatusCode::CREATED,
+            Json(CreatedApiKey {
+                key,
+                info: ApiKeyInfo::from(api_key),
+            }),
+        )),
+        Err(err) => {
+            eprintln!("Database error: {}", err);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to create API key".to_string(),
+            )
+                .into_response())
+        }
+    }
+}
+
+/// Revoke an API key (requires authentication)
+///
+/// Revokes one of your API keys. Admins can revoke any user's keys.
+#[utoipa::path(
+    delete,
+    path = "/api/v1/api-keys/{id}",
+    params(
+        ("id" = i64, Path, description = "API key database ID to revoke")
+    ),
+    responses(
+        (status = 204, description = "API key successfully revoked"),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "API keys cannot manage API keys"),
+        (status = 404, description = "API key not found"),
+        (status = 500, description = "Internal server error")
+    ),
+    tag = "api-keys",
+    security(
+        ("bearer_auth" = [])
+    )
+)]
+pub async fn revoke_api_key(
+    claims: Claims,
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+) -> Result<StatusCode, axum::response::Response> {
+    if claims.api_key_id.is_some() {
+        return Err(AuthError::Forbidden.into_response());
+    }
+    let user_id = claims.user_id().map_err(IntoResponse::into_response)?;
+    let owner_id = (!claims.has_role(Role::Admin)).then_some(user_id);
+
+    match db::revoke_api_key(&state.pool, id, owner_id).await {
+        Ok(true) => Ok(StatusCode::NO_CONTENT),
+        Ok(false) => Err((
+            StatusCode::NOT_FOUND,
+            format!("API key with ID {} not found", id),
+        )
+            .into_response()),
+        Err(err) => {
+            eprintln!("Database error: {}", err);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to revoke API key".to_string(),
+            )
+                .into_response())
+        }
+    }
+}
+
+/// Account of the authenticated user, for endpoints that manage its own credentials
+async fn own_account(
+    state: &AppState,
+    claims: &Claims,
+) -> Result<db::User, axum::response::Response> {
+    // Like API keys, two-factor settings can only be managed after a full login
+    if claims.api_key_id.is_some() {
+        return Err(AuthError::Forbidden.into_response());
+    }
+    let user_id = claims.user_id().map_err(IntoResponse::into_response)?;
+
+    match db::get_user_by_id(&state.pool, user_id).await {
+        Ok(Some(user)) => Ok(user),
+        Ok(None) => Err(AuthError::InvalidToken.into_response()),
+        Err(err) => {
+            eprintln!("Database error: {}", err);
+            Err(AuthError::Database.into_response())
+        }
+    }
+}
+
+/// Start setting up two-factor authentication (requires authentication)
+///
+/// Generates a new TOTP secret and returns it with an `otpauth://` URI and QR code for an authenticator app.
+/// Two-factor authentication is enabled once a code from the app is sent to `/api/v1/me/totp/confirm`. Until then,
+/// editors and admins keep their role but cannot delete or purge quotes.
+#[utoipa::path(
+    post,
+    path = "/api/v1/me/totp",
+    responses(
+        (status = 201, description = "TOTP secret generated", body = TotpEnrollment),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Request used an API key"),
+        (status = 409, description = "Two-factor authentication is already enabled"),
+        (status = 500, description = "Internal server error")
+    ),
+    tag = "two-factor",
+    security(
+        ("bearer_auth" = [])
+    )
+)]
+pub async fn begin_totp(
+    claims: Claims,
+    State(state): State<AppState>,
+) -> Result<(StatusCode, Json<TotpEnrollment>), axum::response::Response> {
+    let user = own_account(&state, &claims).await?;
+
+    match totp::begin_enrollment(&state.pool, &user).await {
+        Ok(enrollment) => Ok((StatusCode::CREATED, Json(enrollment))),
+        Err(e) => Err(e.into_response()),
+    }
+}
+
+/// Enable two-factor authentication (requires authentication)
+///
+/// Checks a code from the authenticator app against the secret from `POST /api/v1/me/totp` and enables two-factor
+/// authentication. Returns recovery codes, each usable once in place of a code; they are only shown here. Editors and
+/// admins can delete and purge quotes once they log in again with a code.
+#[utoipa::path(
+    post,
+    path = "/api/v1/me/totp/confirm",
+    request_body = TotpCodeRequest,
+    responses(
+        (status = 200, description = "Two-factor authentication enabled", body = RecoveryCodes),
+        (status = 400, description = "No TOTP secret was generated"),
+        (status = 401, description = "Unauthorized or wrong code"),
+        (status = 403, description = "Request used an API key"),
+        (status = 409, description = "Two-factor authentication is already enabled"),
+        (status = 500, description = "Internal server error")
+    ),
+    tag = "two-factor",
+    security(
+        ("bearer_auth" = [])
+    )
+)]
+pub async fn confirm_totp(
+    claims: Claims,
+    State(state): State<AppState>,
+    Json(request): Json<TotpCodeRequest>,
+) -> Result<Json<RecoveryCodes>, axum::response::Response> {
+    let user = own_account(&state, &claims).await?;
+
+    match totp::confirm_enrollment(&state.pool, &user, &request.code).await {
+        Ok(codes) => Ok(Json(codes)),
+        Err(e) => Err(e.into_response()),
+    }
+}
+
+/// Disable two-factor authentication (requires authentication)
+///
+/// Turns two-factor authentication off after checking a current code or recovery code. Editors and admins keep their
+/// role, but deleting and purging quotes is refused from the next request until they set it up anew.
+#[utoipa::path(
+    delete,
+    path = "/api/v1/me/totp",
+    request_body = TotpCodeRequest,
+    responses(
+        (status = 204, description = "Two-factor authentication disabled"),
+        (status = 400, description = "Two-factor authentication is not enabled"),
+        (status = 401, description = "Unauthorized or wrong code"),
+        (status = 403, description = "Request used an API key"),
+        (status = 500, description = "Internal server error")
+    ),
+    tag = "two-factor",
+    security(
+        ("bearer_auth" = [])
+    )
+)]
+pub async fn disable_totp(
+    claims: Claims,
+    State(state): State<AppState>,
+    Json(request): Json<TotpCodeRequest>,
+) -> Result<StatusCode, axum::response::Response> {
+    let user = own_account(&state, &claims).await?;
+
+    match totp::disable(&state.pool, &user, &request.code).await {
+        Ok(()) => Ok(StatusCode::NO_CONTENT),
+        Err(e) => Err(e.into_response()),
+    }
+}
+
+/// List all user accounts (requires admin role)
+///
+/// Returns every registered user with their role and account status.
+#[utoipa::path(
+    get,
+    path = "/api/v1/users",
+    responses(
+        (status = 200, description = "List of users successfully retrieved", body = Vec<UserProfile>),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Requires admin role"),
+        (status = 500, description = "Internal server error")
+    ),
+    tag = "users",
+    security(
+        ("bearer_auth" = []),
+        ("api_key" = ["admin"])
+    )
+)]
+pub async fn list_users(
+    _auth: RequireRole<Admin>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<UserProfile>>, (StatusCode, String)> {
+    match db::list_users(&state.pool).await {
+        Ok(users) => Ok(Json(users.into_iter().map(UserProfile::from).collect())),
+        Err(err) => {
+            eprintln!("Database error: {}", err);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to retrieve users".to_string(),
+            ))
+        }
+    }
+}
+
+/// Change a user's role or disable their account (requires admin role)
+///
+/// Fields left out of the request body are unchanged. Disabled users can no longer log in
+/// and their outstanding tokens are rejected.
+#[utoipa::path(
+    put,
+    path = "/api/v1/users/{id}",
+    params(
+        ("id" = i64, Path, description = "User database ID to update")
+    ),
+    request_body = UpdateUserRequest,
+    responses(
+        (status = 200, description = "User successfully updated", body = UserProfile),
+        (status = 400, description = "Invalid request body"),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Requires admin role"),
+        (status = 404, description = "User not found"),
+        (status = 500, description = "Internal server error")
+    ),
+    tag = "users",
+    security(
+        ("bearer_auth" = []),
+        ("api_key" = ["admin"])
+    )
+)]
+pub async fn update_user(
+    auth: RequireRole<Admin>,
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    Json(request): Json<UpdateUserRequest>,
+) -> Result<Json<UserProfile>, (StatusCode, String)> {
+    // Keep admins from locking themselves out
+    if auth.claims.user_id().ok() == Some(id)
+        && (request.disabled == Some(true) || request.role.is_some_and(|r| r != Role::Admin))
+    {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "Admins cannot disable or demote their own account".to_string(),
+        ));
+    }
+
+    match db::update_user(&state.pool, id, request).await {
+        Ok(Some(user)) => {
+            // Disabled users lose their sessions so they cannot refresh their way back in
+            if user.disabled
+                && let Err(err) = db::revoke_user_sessions(&state.pool, user.id).await
+            {
+                eprintln!("Database error: {}", err);
+            }
+            Ok(Json(UserProfile::from(user)))
+        }
+        Ok(None) => Err((
+            StatusCode::NOT_FOUND,
+            format!("User with ID {} not found", id),
+        )),
+        Err(err) => {
+            eprintln!("Database error: {}", err);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to update user".to_string(),
+            ))
+        }
+    }
+}
+
+/// Restrict a redirect target to a path on this site. Browsers read backslashes
+/// as slashes and drop tabs and line breaks, so paths containing them are refused.
+pub fn local_path(path: &str) -> &str {
+    if path.starts_with('/')
+        && !path.starts_with("//")
+        && !path.contains(|c: char| c == '\\' || c.is_control())
+    {
+        path
+    } else {
+        "/"
+    }
+}
+
+/// Create API router with all quote-related endpoints
+pub fn create_api_router() -> utoipa_axum::router::OpenApiRouter<AppState> {
+    utoipa_axum::router::OpenApiRouter::new()
+        .route("/auth", post(login))
+        .route("/auth/totp", post(login_totp))
+        .route("/auth/refresh", post(refresh))
+        .route("/auth/logout", post(logout))
+        .route("/auth/register", post(register))
+        .route("/auth/oidc/login", get(oidc_login))
+        .route("/auth/oidc/callback", get(oidc_callback))
+        .route("/.well-known/jwks.json", get(jwks))
+        .route("/api/v1/quotes", get(get_all_quotes).post(create_quote))
+        .route("/api/v1/quotes/random", get(get_random_quote))
+        .route(
+            "/api/v1/quotes/{id}",
+            get(get_quote_by_id)
+                .put(update_quote)
+                .patch(patch_quote)
+                .delete(delete_quote),
+        )
+        .route("/api/v1/quotes/{id}/revisions", get(list_revisions))
+        .route(
+            "/api/v1/quotes/{id}/revisions/{revision}",
+            get(get_revision),
+        )
+        .route(
+            "/api/v1/quotes/{id}/revisions/{revision}/restore",
+            post(restore_revision),
+        )
+        .route("/api/v1/quotes/{id}/diff", get(diff_revisions))
+        .route("/api/v1/trash", get(list_trash))
+        .route("/api/v1/trash/{id}", delete(purge_quote))
+        .route("/api/v1/trash/{id}/restore", post(restore_quote))
+        .route("/api/v1/tags", get(list_tags))
+        .route("/api/v1/tags/{id}", put(rename_tag))
+        .route("/api/v1/tags/{id}/merge", post(merge_tag))
+        .route("/api/v1/authors", get(list_authors).post(create_author))
+        .route(
+            "/api/v1/authors/{id}",
+            get(get_author).put(update_author).delete(delete_author),
         )
+        .route("/api/v1/keys/reload", post(reload_keys))
+        .route("/api/v1/api-keys", get(list_api_keys).post(create_api_key))
+        .route("/api/v1/api-keys/{id}", delete(revoke_api_key))
+        .route("/api/v1/me/quotes", get(get_my_quotes))
+        .route("/api/v1/me/totp", post(begin_totp).delete(disable_totp))
+        .route("/api/v1/me/totp/confirm", post(confirm_totp))
+        .route("/api/v1/users", get(list_users))
+        .route("/api/v1/users/{id}", put(update_user))
 }
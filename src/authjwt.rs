@@ -1,40 +1,498 @@
 //! JWT authentication module for the Quotes Server.
 //!
-//! Provides JWT token generation, validation, and user registration functionality.
+//! Provides JWT token generation, validation, password hashing, refresh-token
+//! sessions, web UI session cookies with CSRF protection, API keys, and the
+//! extractor that resolves bearer tokens, API keys or session cookies to active
+//! user accounts.
+//! Tokens are signed with either a shared HMAC secret or an Ed25519/RSA key pair,
+//! whose public half is published as a JWK set.
 //!
+use crate::{
+    AppState,
+    db::{self, SessionKind, User},
+};
+use argon2::{
+    Argon2,
+    password_hash::{
+        PasswordHash, PasswordHasher, PasswordVerifier, SaltString,
+        rand_core::{OsRng, RngCore},
+    },
+};
 use axum::{
-    Json,
-    extract::FromRequestParts,
-    http::{StatusCode, request::Parts},
+    Form, Json,
+    body::Body,
+    extract::{FromRequest, FromRequestParts, Request, State},
+    http::{HeaderMap, HeaderValue, Method, StatusCode, header, request::Parts},
+    middleware::Next,
     response::{IntoResponse, Response},
 };
-use chrono::{TimeDelta, Utc};
-use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, Validation, decode, encode};
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use chrono::{DateTime, TimeDelta, Utc};
+use jsonwebtoken::{
+    Algorithm, DecodingKey, EncodingKey, Header, Validation, decode, decode_header, encode,
+    jwk::{
+        AlgorithmParameters, CommonParameters, EllipticCurve, Jwk, JwkSet, KeyAlgorithm,
+        OctetKeyPairParameters, OctetKeyPairType, PublicKeyUse, RSAKeyParameters, RSAKeyType,
+    },
+};
+use rsa::{
+    RsaPublicKey, pkcs1::DecodeRsaPublicKey, pkcs8::DecodePublicKey, traits::PublicKeyParts,
+};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::SqlitePool;
+use std::{
+    marker::PhantomData,
+    path::{Path, PathBuf},
+    sync::{Arc, LazyLock, RwLock},
+};
 use utoipa::ToSchema;
 
-/// JWT signing and verification keys
+/// Lifetime of an access token
+const ACCESS_TOKEN_TTL: TimeDelta = TimeDelta::minutes(15);
+
+/// Lifetime of a refresh token, extended each time it is used
+const REFRESH_TOKEN_TTL: TimeDelta = TimeDelta::days(30);
+
+/// Lifetime of a web UI session cookie
+const WEB_SESSION_TTL: TimeDelta = TimeDelta::days(7);
+
+/// Cookie holding the web UI session token
+pub const SESSION_COOKIE: &str = "session";
+
+/// Cookie holding the CSRF token that forms and cookie-authenticated requests must echo
+pub const CSRF_COOKIE: &str = "csrf_token";
+
+/// Header carrying the CSRF token on cookie-authenticated API requests
+const CSRF_HEADER: &str = "x-csrf-token";
+
+/// DER prefix of an Ed25519 SubjectPublicKeyInfo, followed by the 32-byte key
+const ED25519_SPKI_PREFIX: [u8; 12] = [
+    0x30, 0x2a, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x03, 0x21, 0x00,
+];
+
+/// A single key in the [`JwtKeys`] ring
 #[derive(Clone)]
-pub struct JwtKeys {
+pub struct SigningKey {
+    /// Key ID placed in the `kid` header of tokens signed with this key
+    pub kid: String,
     pub encoding: EncodingKey,
     pub decoding: DecodingKey,
+    pub algorithm: Algorithm,
+    /// Public verification key, absent when signing with a shared secret
+    pub jwk: Option<Jwk>,
 }
 
-impl JwtKeys {
-    pub fn new(secret: &[u8]) -> Self {
+impl SigningKey {
+    /// HMAC (HS512) key from a shared secret
+    pub fn from_secret(kid: String, secret: &[u8]) -> Self {
         Self {
+            kid,
             encoding: EncodingKey::from_secret(secret),
             decoding: DecodingKey::from_secret(secret),
+            algorithm: Algorithm::HS512,
+            jwk: None,
         }
     }
+
+    /// Load an Ed25519 (EdDSA) or RSA (RS256) key pair from PEM-encoded keys.
+    ///
+    /// The private key must be PKCS#8 (or PKCS#1 for RSA) and the public key
+    /// SubjectPublicKeyInfo (or PKCS#1 for RSA).
+    pub fn from_pem(
+        kid: String,
+        private_pem: &[u8],
+        public_pem: &[u8],
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let public = pem::parse(public_pem)?;
+        let der = public.contents();
+
+        let (algorithm, encoding, decoding, params) =
+            if der.len() == 44 && der.starts_with(&ED25519_SPKI_PREFIX) {
+                let x = URL_SAFE_NO_PAD.encode(&der[ED25519_SPKI_PREFIX.len()..]);
+                (
+                    Algorithm::EdDSA,
+                    EncodingKey::from_ed_pem(private_pem)?,
+                    DecodingKey::from_ed_pem(public_pem)?,
+                    AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+                        key_type: OctetKeyPairType::OctetKeyPair,
+                        curve: EllipticCurve::Ed25519,
+                        x,
+                    }),
+                )
+            } else {
+                let rsa_key = if public.tag() == "RSA PUBLIC KEY" {
+                    RsaPublicKey::from_pkcs1_der(der)?
+                } else {
+                    RsaPublicKey::from_public_key_der(der)?
+                };
+                (
+                    Algorithm::RS256,
+                    EncodingKey::from_rsa_pem(private_pem)?,
+                    DecodingKey::from_rsa_pem(public_pem)?,
+                    AlgorithmParameters::RSA(RSAKeyParameters {
+                        key_type: RSAKeyType::RSA,
+                        n: URL_SAFE_NO_PAD.encode(rsa_key.n().to_bytes_be()),
+                        e: URL_SAFE_NO_PAD.encode(rsa_key.e().to_bytes_be()),
+                    }),
+                )
+            };
+
+        let key_algorithm = match algorithm {
+            Algorithm::EdDSA => KeyAlgorithm::EdDSA,
+            _ => KeyAlgorithm::RS256,
+        };
+        let jwk = Jwk {
+            common: CommonParameters {
+                public_key_use: Some(PublicKeyUse::Signature),
+                key_algorithm: Some(key_algorithm),
+                key_id: Some(kid.clone()),
+                ..Default::default()
+            },
+            algorithm: params,
+        };
+
+        let key = Self {
+            kid,
+            encoding,
+            decoding,
+            algorithm,
+            jwk: Some(jwk),
+        };
+
+        // Catch mismatched key files at load time rather than on first login
+        let exp = (Utc::now() + TimeDelta::minutes(1)).timestamp();
+        let probe = encode(
+            &Header::new(algorithm),
+            &serde_json::json!({ "exp": exp }),
+            &key.encoding,
+        )?;
+        decode::<serde_json::Value>(&probe, &key.decoding, &Validation::new(algorithm))?;
+
+        Ok(key)
+    }
+}
+
+/// Where the key ring is loaded from, so it can be reloaded at runtime
+#[derive(Clone)]
+enum KeySource {
+    /// Keys supplied directly by tests; reloading keeps them as they are
+    #[cfg(test)]
+    Fixed,
+    /// Shared secret read from a file
+    Secret(PathBuf),
+    /// Single PEM key pair
+    Pem { private: PathBuf, public: PathBuf },
+    /// Directory of `<kid>.secret` files and `<kid>.pem`/`<kid>.pub.pem` pairs
+    Dir(PathBuf),
+}
+
+/// Ring of JWT signing keys.
+///
+/// New tokens are signed with the newest key, while tokens signed with any
+/// other key still in the ring keep validating until that key is retired.
+#[derive(Clone)]
+pub struct JwtKeys {
+    /// Keys ordered from oldest to newest; never empty
+    ring: Arc<RwLock<Vec<SigningKey>>>,
+    source: KeySource,
+}
+
+impl JwtKeys {
+    /// Single HMAC key, identified by its fingerprint
+    #[cfg(test)]
+    pub fn new(secret: &[u8]) -> Self {
+        let kid = fingerprint(secret);
+        Self::from_keys(vec![SigningKey::from_secret(kid, secret)], KeySource::Fixed)
+    }
+
+    /// Single Ed25519 or RSA key pair, identified by its public key fingerprint
+    #[cfg(test)]
+    pub fn from_pem(
+        private_pem: &[u8],
+        public_pem: &[u8],
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let key = SigningKey::from_pem(fingerprint(public_pem), private_pem, public_pem)?;
+        Ok(Self::from_keys(vec![key], KeySource::Fixed))
+    }
+
+    /// Key ring holding the shared secret read from a file
+    pub async fn from_secret_file(path: PathBuf) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::load(KeySource::Secret(path)).await
+    }
+
+    /// Key ring loaded from a directory of `<kid>.secret` files and
+    /// `<kid>.pem`/`<kid>.pub.pem` pairs; the greatest key ID signs
+    pub async fn from_dir(dir: PathBuf) -> Result<Self, Box<dyn std::error::Error>> {
+        let keys = load_key_dir(&dir).await?;
+        Ok(Self::from_keys(keys, KeySource::Dir(dir)))
+    }
+
+    fn from_keys(keys: Vec<SigningKey>, source: KeySource) -> Self {
+        Self {
+            ring: Arc::new(RwLock::new(keys)),
+            source,
+        }
+    }
+
+    async fn load(source: KeySource) -> Result<Self, Box<dyn std::error::Error>> {
+        let keys = Self::from_keys(Vec::new(), source);
+        keys.reload().await?;
+        Ok(keys)
+    }
+
+    /// Key used to sign new tokens
+    pub fn signing_key(&self) -> SigningKey {
+        let ring = self.ring.read().unwrap();
+        ring.last().cloned().expect("key ring is never empty")
+    }
+
+    /// Key for verifying a token with the given `kid`. Tokens without a
+    /// `kid` predate key rotation and are checked against the signing key.
+    pub fn verification_key(&self, kid: Option<&str>) -> Option<SigningKey> {
+        let ring = self.ring.read().unwrap();
+        match kid {
+            Some(kid) => ring.iter().find(|key| key.kid == kid).cloned(),
+            None => ring.last().cloned(),
+        }
+    }
+
+    /// IDs of all keys in the ring, oldest first
+    pub fn key_ids(&self) -> Vec<String> {
+        let ring = self.ring.read().unwrap();
+        ring.iter().map(|key| key.kid.clone()).collect()
+    }
+
+    /// Public keys for offline token verification, in JWK set format
+    pub fn jwks(&self) -> JwkSet {
+        let ring = self.ring.read().unwrap();
+        JwkSet {
+            keys: ring.iter().filter_map(|key| key.jwk.clone()).collect(),
+        }
+    }
+
+    /// Re-read the key files and swap in the new ring.
+    ///
+    /// For a key directory, keys no longer present are retired. A single secret
+    /// or key pair that changed takes over signing, while the key it replaces is
+    /// kept for verification until the next change retires it. On error the
+    /// current ring is kept.
+    pub async fn reload(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let key = match &self.source {
+            #[cfg(test)]
+            KeySource::Fixed => return Ok(()),
+            KeySource::Secret(path) => {
+                let secret = tokio::fs::read_to_string(path).await?;
+                let secret = secret.trim().as_bytes();
+                SigningKey::from_secret(fingerprint(secret), secret)
+            }
+            KeySource::Pem { private, public } => {
+                let private_pem = tokio::fs::read(private).await?;
+                let public_pem = tokio::fs::read(public).await?;
+                let kid = fingerprint(&public_pem);
+                SigningKey::from_pem(kid, &private_pem, &public_pem)?
+            }
+            KeySource::Dir(dir) => {
+                let keys = load_key_dir(dir).await?;
+                *self.ring.write().unwrap() = keys;
+                return Ok(());
+            }
+        };
+
+        // Key IDs are fingerprints, so an unchanged file keeps the same ID
+        let mut ring = self.ring.write().unwrap();
+        if ring.last().is_some_and(|current| current.kid == key.kid) {
+            return Ok(());
+        }
+        let previous = ring.pop();
+        *ring = previous.into_iter().chain([key]).collect();
+        Ok(())
+    }
+}
+
+/// Short stable key ID derived from key material
+fn fingerprint(bytes: &[u8]) -> String {
+    to_hex(&Sha256::digest(bytes)[..8])
+}
+
+/// Load every key in a key directory, ordered by key ID
+async fn load_key_dir(dir: &Path) -> Result<Vec<SigningKey>, Box<dyn std::error::Error>> {
+    let mut keys = Vec::new();
+    let mut entries = tokio::fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+
+        if let Some(kid) = name.strip_suffix(".secret") {
+            let secret = tokio::fs::read_to_string(&path).await?;
+            keys.push(SigningKey::from_secret(
+                kid.to_string(),
+                secret.trim().as_bytes(),
+            ));
+        } else if let Some(kid) = name.strip_suffix(".pem")
+            && !kid.ends_with(".pub")
+        {
+            let private_pem = tokio::fs::read(&path).await?;
+            let public_pem = tokio::fs::read(dir.join(format!("{kid}.pub.pem"))).await?;
+            keys.push(SigningKey::from_pem(
+                kid.to_string(),
+                &private_pem,
+                &public_pem,
+            )?);
+        }
+    }
+
+    if keys.is_empty() {
+        return Err(format!("no signing keys found in {}", dir.display()).into());
+    }
+    keys.sort_by(|a, b| a.kid.cmp(&b.kid));
+    Ok(keys)
+}
+
+/// User roles, ordered from least to most privileged
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, ToSchema, sqlx::Type,
+)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(rename_all = "lowercase")]
+pub enum Role {
+    /// Can authenticate and read quotes
+    Viewer,
+    /// Can also create quotes
+    Contributor,
+    /// Can also edit and delete any quote
+    Editor,
+    /// Can also manage user accounts
+    Admin,
+}
+
+/// Permissions an API key can be limited to, each matching a [`MinimumRole`] guard
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub enum Scope {
+    /// Create quotes (contributor role)
+    #[serde(rename = "quotes:create")]
+    QuotesCreate,
+    /// Edit and delete quotes; contributors' keys only reach their own quotes
+    #[serde(rename = "quotes:edit")]
+    QuotesEdit,
+    /// Administrative endpoints (admin role)
+    #[serde(rename = "admin")]
+    Admin,
+}
+
+impl Scope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scope::QuotesCreate => "quotes:create",
+            Scope::QuotesEdit => "quotes:edit",
+            Scope::Admin => "admin",
+        }
+    }
+
+    /// Role a user needs to grant this scope to an API key
+    pub fn role(&self) -> Role {
+        match self {
+            Scope::QuotesCreate | Scope::QuotesEdit => Role::Contributor,
+            Scope::Admin => Role::Admin,
+        }
+    }
+
+    /// Parse a space-separated scope list as stored in the database
+    pub fn parse_list(scopes: &str) -> Vec<Scope> {
+        scopes
+            .split_whitespace()
+            .filter_map(|scope| match scope {
+                "quotes:create" => Some(Scope::QuotesCreate),
+                "quotes:edit" => Some(Scope::QuotesEdit),
+                "admin" => Some(Scope::Admin),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Format scopes as a space-separated list for storage
+    pub fn join(scopes: &[Scope]) -> String {
+        scopes
+            .iter()
+            .map(Scope::as_str)
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
 }
 
 /// JWT claims structure
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct Claims {
     pub iss: String, // issuer
-    pub sub: String, // subject (user identifier)
+    pub sub: String, // subject (user ID)
     pub exp: u64,    // expiration time
+    pub jti: String, // unique token ID, used for revocation
+    pub sid: String, // login session the token belongs to
+    pub role: Role,  // role at the time the token was issued
+    /// API key the request was authenticated with, if not a bearer token
+    #[serde(skip)]
+    pub api_key_id: Option<i64>,
+    /// Scopes the API key is limited to; bearer tokens are unrestricted
+    #[serde(skip)]
+    pub scopes: Option<Vec<Scope>>,
+    /// Whether the account has two-factor authentication enabled, looked up
+    /// on each request
+    #[serde(skip)]
+    pub totp_enabled: bool,
+    /// Whether the login that started the session passed a TOTP code. Never
+    /// set for API keys.
+    #[serde(default)]
+    pub second_factor: bool,
+}
+
+impl Claims {
+    /// Database ID of the user this token was issued to
+    pub fn user_id(&self) -> Result<i64, AuthError> {
+        self.sub.parse().map_err(|_| AuthError::InvalidToken)
+    }
+
+    /// Expiration time as a timestamp
+    pub fn expires_at(&self) -> DateTime<Utc> {
+        i64::try_from(self.exp)
+            .ok()
+            .and_then(|exp| DateTime::from_timestamp(exp, 0))
+            .unwrap_or_else(Utc::now)
+    }
+
+    /// Whether the token holder has at least the given role
+    pub fn has_role(&self, role: Role) -> bool {
+        self.role >= role
+    }
+
+    /// Whether the request may use endpoints guarded by the given scope
+    pub fn has_scope(&self, scope: Scope) -> bool {
+        self.scopes
+            .as_ref()
+            .is_none_or(|scopes| scopes.contains(&scope))
+    }
+
+    /// Whether the request may edit and delete a quote created by `created_by`.
+    /// Editors may change any quote, contributors only their own.
+    pub fn may_modify(&self, created_by: Option<i64>) -> bool {
+        if !self.has_scope(Scope::QuotesEdit) {
+            return false;
+        }
+        self.has_role(Role::Editor)
+            || (self.has_role(Role::Contributor)
+                && created_by.is_some_and(|owner| self.user_id().is_ok_and(|id| id == owner)))
+    }
+
+    /// Editors and admins can delete any quote, so deleting needs their account
+    /// to have two-factor authentication enabled and the session to have been
+    /// started with a TOTP code. Their API keys cannot delete quotes.
+    pub fn check_second_factor(&self) -> Result<(), AuthError> {
+        if self.has_role(Role::Editor) && !(self.totp_enabled && self.second_factor) {
+            return Err(AuthError::TotpRequired);
+        }
+        Ok(())
+    }
 }
 
 /// User registration request
@@ -43,6 +501,41 @@ pub struct Registration {
     pub full_name: String,
     pub email: String,
     pub password: String,
+    /// Registration key handed out to team members by the server operator
+    pub reg_key: String,
+}
+
+/// User login request
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Credentials {
+    pub email: String,
+    pub password: String,
+}
+
+/// Key IDs currently in the signing key ring
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct KeyRingStatus {
+    /// Key used to sign new tokens
+    #[schema(example = "2026-10-16")]
+    pub signing_key: String,
+    /// All keys accepted for verification, oldest first
+    #[schema(example = json!(["2026-07-01", "2026-10-16"]))]
+    pub keys: Vec<String>,
+}
+
+impl From<&JwtKeys> for KeyRingStatus {
+    fn from(jwt_keys: &JwtKeys) -> Self {
+        Self {
+            signing_key: jwt_keys.signing_key().kid,
+            keys: jwt_keys.key_ids(),
+        }
+    }
+}
+
+/// Token refresh request
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct RefreshRequest {
+    pub refresh_token: String,
 }
 
 /// Authentication response body containing JWT token
@@ -50,13 +543,19 @@ pub struct Registration {
 pub struct AuthBody {
     pub access_token: String,
     pub token_type: String,
+    /// Seconds until the access token expires
+    pub expires_in: i64,
+    /// Single-use token for obtaining a new access token from `/auth/refresh`
+    pub refresh_token: String,
 }
 
 impl AuthBody {
-    pub fn new(access_token: String) -> Self {
+    pub fn new(access_token: String, refresh_token: String) -> Self {
         Self {
             access_token,
             token_type: "Bearer".to_string(),
+            expires_in: ACCESS_TOKEN_TTL.num_seconds(),
+            refresh_token,
         }
     }
 }
@@ -65,27 +564,75 @@ impl AuthBody {
 #[derive(Debug)]
 pub enum AuthError {
     TokenCreation,
+    PasswordHashing,
     InvalidToken,
     WrongCredentials,
     MissingCredentials,
     TokenExpired,
+    TokenRevoked,
+    AccountDisabled,
+    EmailTaken,
+    Forbidden,
+    InvalidCsrfToken,
+    /// Locked out after repeated failures; holds the seconds until the next attempt
+    TooManyAttempts(i64),
+    TotpAlreadyEnabled,
+    TotpNotEnabled,
+    /// Editors and admins must enable two-factor authentication first
+    TotpRequired,
+    Database,
 }
 
 impl IntoResponse for AuthError {
     fn into_response(self) -> Response {
+        let retry_after = match self {
+            AuthError::TooManyAttempts(secs) => Some(secs),
+            _ => None,
+        };
         let (status, error_message) = match self {
             AuthError::TokenCreation => {
                 (StatusCode::INTERNAL_SERVER_ERROR, "Token creation failed")
             }
+            AuthError::PasswordHashing => {
+                (StatusCode::INTERNAL_SERVER_ERROR, "Password hashing failed")
+            }
             AuthError::InvalidToken => (StatusCode::BAD_REQUEST, "Invalid token"),
             AuthError::WrongCredentials => (StatusCode::UNAUTHORIZED, "Wrong credentials"),
             AuthError::MissingCredentials => (StatusCode::BAD_REQUEST, "Missing credentials"),
             AuthError::TokenExpired => (StatusCode::UNAUTHORIZED, "Token expired"),
+            AuthError::TokenRevoked => (StatusCode::UNAUTHORIZED, "Token revoked"),
+            AuthError::AccountDisabled => (StatusCode::FORBIDDEN, "Account disabled"),
+            AuthError::EmailTaken => (StatusCode::CONFLICT, "Email already registered"),
+            AuthError::Forbidden => (StatusCode::FORBIDDEN, "Insufficient permissions"),
+            AuthError::InvalidCsrfToken => (StatusCode::FORBIDDEN, "Invalid CSRF token"),
+            AuthError::TooManyAttempts(_) => (
+                StatusCode::TOO_MANY_REQUESTS,
+                "Too many failed attempts, try again later",
+            ),
+            AuthError::TotpAlreadyEnabled => (
+                StatusCode::CONFLICT,
+                "Two-factor authentication is already enabled",
+            ),
+            AuthError::TotpNotEnabled => (
+                StatusCode::BAD_REQUEST,
+                "Two-factor authentication is not set up",
+            ),
+            AuthError::TotpRequired => (
+                StatusCode::FORBIDDEN,
+                "Two-factor authentication is required for this action",
+            ),
+            AuthError::Database => (StatusCode::INTERNAL_SERVER_ERROR, "Internal server error"),
         };
         let body = Json(serde_json::json!({
             "error": error_message,
         }));
-        (status, body).into_response()
+        let mut response = (status, body).into_response();
+        if let Some(secs) = retry_after {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(secs));
+        }
+        response
     }
 }
 
@@ -99,40 +646,488 @@ pub async fn read_secret(
     Ok(secret.trim().to_string())
 }
 
-/// Generate JWT keys from secret
+/// Load the JWT key ring from the first configured source: a key directory
+/// (`JWT_KEYS_DIR`), a PEM key pair (`JWT_PRIVATE_KEY` and `JWT_PUBLIC_KEY`),
+/// or the shared secret file (`JWT_SECRET`)
 pub async fn make_jwt_keys() -> Result<JwtKeys, Box<dyn std::error::Error>> {
-    let secret = read_secret("JWT_SECRET", "./credentials.txt").await?;
-    Ok(JwtKeys::new(secret.as_bytes()))
+    if let Ok(dir) = std::env::var("JWT_KEYS_DIR") {
+        return JwtKeys::from_dir(dir.into()).await;
+    }
+
+    if let (Ok(private), Ok(public)) = (
+        std::env::var("JWT_PRIVATE_KEY"),
+        std::env::var("JWT_PUBLIC_KEY"),
+    ) {
+        return JwtKeys::load(KeySource::Pem {
+            private: private.into(),
+            public: public.into(),
+        })
+        .await;
+    }
+
+    let path = std::env::var("JWT_SECRET").unwrap_or_else(|_| "./credentials.txt".to_owned());
+    JwtKeys::from_secret_file(path.into()).await
+}
+
+/// Hash a password with Argon2 using a fresh random salt
+pub async fn hash_password(password: String) -> Result<String, AuthError> {
+    tokio::task::spawn_blocking(move || {
+        let salt = SaltString::generate(&mut OsRng);
+        Argon2::default()
+            .hash_password(password.as_bytes(), &salt)
+            .map(|hash| hash.to_string())
+    })
+    .await
+    .map_err(|_| AuthError::PasswordHashing)?
+    .map_err(|_| AuthError::PasswordHashing)
+}
+
+/// Argon2 hash of a random password, checked for logins naming an unknown
+/// account so that they take as long as logins naming a real one
+static DUMMY_PASSWORD_HASH: LazyLock<String> = LazyLock::new(|| {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(random_token(32).as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .unwrap_or_default()
+});
+
+/// Check a password against a stored Argon2 hash. Without a stored hash the
+/// password is refused, after the same work against a dummy hash.
+pub async fn verify_password(password: String, password_hash: Option<String>) -> bool {
+    tokio::task::spawn_blocking(move || {
+        let known = password_hash.is_some();
+        let password_hash = password_hash.unwrap_or_else(|| DUMMY_PASSWORD_HASH.clone());
+        let verified = PasswordHash::new(&password_hash)
+            .map(|hash| {
+                Argon2::default()
+                    .verify_password(password.as_bytes(), &hash)
+                    .is_ok()
+            })
+            .unwrap_or(false);
+        known && verified
+    })
+    .await
+    .unwrap_or(false)
+}
+
+/// Generate a random hex string from `len` bytes of OS randomness
+pub fn random_token(len: usize) -> String {
+    let mut bytes = vec![0u8; len];
+    OsRng.fill_bytes(&mut bytes);
+    to_hex(&bytes)
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Refresh tokens and API keys are stored as SHA-256 digests so a leaked
+/// database cannot be replayed
+pub fn hash_token(token: &str) -> String {
+    to_hex(&Sha256::digest(token.as_bytes()))
 }
 
-/// Generate JWT token for user registration
+/// Generate a short-lived JWT access token for a user's session
 pub fn make_jwt_token(
     jwt_keys: &JwtKeys,
-    reg_key: &str,
-    registration: &Registration,
-) -> Result<AuthBody, AuthError> {
-    if registration.password != reg_key {
-        return Err(AuthError::WrongCredentials);
+    user: &User,
+    session_id: &str,
+    second_factor: bool,
+) -> Result<String, AuthError> {
+    if user.disabled {
+        return Err(AuthError::AccountDisabled);
     }
 
     let iss = "quote-server.localhost".to_string();
-    let sub = format!("{} <{}>", registration.full_name, registration.email);
-    let exp = (Utc::now() + TimeDelta::days(1)).timestamp();
+    let sub = user.id.to_string();
+    let exp = (Utc::now() + ACCESS_TOKEN_TTL).timestamp();
     let exp = u64::try_from(exp).unwrap();
+    let jti = random_token(16);
+    let sid = session_id.to_string();
+    let role = user.role;
+
+    let claims = Claims {
+        iss,
+        sub,
+        exp,
+        jti,
+        sid,
+        role,
+        api_key_id: None,
+        scopes: None,
+        totp_enabled: user.totp_enabled(),
+        second_factor,
+    };
+    let key = jwt_keys.signing_key();
+    let mut header = Header::new(key.algorithm);
+    header.kid = Some(key.kid);
+    encode(&header, &claims, &key.encoding).map_err(|_| AuthError::TokenCreation)
+}
+
+/// Start a new login session, returning an access token and a refresh token.
+/// `second_factor` tells whether the login passed a TOTP code.
+pub async fn start_session(
+    pool: &SqlitePool,
+    jwt_keys: &JwtKeys,
+    user: &User,
+    second_factor: bool,
+) -> Result<AuthBody, AuthError> {
+    let session_id = random_token(16);
+    let access_token = make_jwt_token(jwt_keys, user, &session_id, second_factor)?;
+
+    let refresh_token = random_token(32);
+    let expires_at = Utc::now() + REFRESH_TOKEN_TTL;
+    db::create_session(
+        pool,
+        &session_id,
+        user.id,
+        &hash_token(&refresh_token),
+        SessionKind::Api,
+        second_factor,
+        expires_at,
+    )
+    .await
+    .map_err(|_| AuthError::Database)?;
+
+    Ok(AuthBody::new(access_token, refresh_token))
+}
+
+/// Attributes of the cookies the web UI sets, with `Secure` when it is served over HTTPS
+fn cookie_attributes(secure: bool) -> &'static str {
+    if secure {
+        "HttpOnly; SameSite=Lax; Secure"
+    } else {
+        "HttpOnly; SameSite=Lax"
+    }
+}
+
+/// Start a web UI session, returning the `Set-Cookie` value for its session cookie.
+/// `second_factor` tells whether the login passed a TOTP code.
+pub async fn start_web_session(
+    pool: &SqlitePool,
+    user: &User,
+    second_factor: bool,
+    secure: bool,
+) -> Result<String, AuthError> {
+    let session_id = random_token(16);
+    let token = random_token(32);
+    db::create_session(
+        pool,
+        &session_id,
+        user.id,
+        &hash_token(&token),
+        SessionKind::Web,
+        second_factor,
+        Utc::now() + WEB_SESSION_TTL,
+    )
+    .await
+    .map_err(|_| AuthError::Database)?;
+
+    Ok(format!(
+        "{}={}; Path=/; {}; Max-Age={}",
+        SESSION_COOKIE,
+        token,
+        cookie_attributes(secure),
+        WEB_SESSION_TTL.num_seconds()
+    ))
+}
+
+/// `Set-Cookie` value that removes the session cookie
+pub fn clear_session_cookie(secure: bool) -> String {
+    format!(
+        "{}=; Path=/; {}; Max-Age=0",
+        SESSION_COOKIE,
+        cookie_attributes(secure)
+    )
+}
+
+/// Resolve a session cookie to claims for its owner
+pub async fn authenticate_web_session(pool: &SqlitePool, token: &str) -> Result<Claims, AuthError> {
+    let session = db::get_web_session(pool, &hash_token(token))
+        .await
+        .map_err(|_| AuthError::Database)?
+        .ok_or(AuthError::InvalidToken)?;
+
+    if session.revoked_at.is_some() {
+        return Err(AuthError::TokenRevoked);
+    }
+    if session.expires_at < Utc::now() {
+        return Err(AuthError::TokenExpired);
+    }
+
+    let user = db::get_user_by_id(pool, session.user_id)
+        .await
+        .map_err(|_| AuthError::Database)?
+        .ok_or(AuthError::InvalidToken)?;
+    if user.disabled {
+        return Err(AuthError::AccountDisabled);
+    }
+
+    Ok(Claims {
+        iss: "quote-server.localhost".to_string(),
+        sub: user.id.to_string(),
+        exp: u64::try_from(session.expires_at.timestamp()).unwrap_or_default(),
+        jti: format!("web-{}", session.id),
+        sid: session.id,
+        role: user.role,
+        api_key_id: None,
+        scopes: None,
+        totp_enabled: user.totp_enabled(),
+        second_factor: session.second_factor,
+    })
+}
+
+/// Check an email and password, returning the account they belong to
+pub async fn authenticate_password(
+    pool: &SqlitePool,
+    email: &str,
+    password: String,
+) -> Result<User, AuthError> {
+    let email = email.trim().to_lowercase();
+    if email.is_empty() || password.is_empty() {
+        return Err(AuthError::MissingCredentials);
+    }
+
+    let user = match db::get_user_by_email(pool, &email).await {
+        Ok(user) => user,
+        Err(err) => {
+            eprintln!("Database error: {}", err);
+            return Err(AuthError::Database);
+        }
+    };
+
+    // Unknown addresses still go through Argon2, so timing does not reveal which accounts exist
+    let password_hash = user.as_ref().map(|user| user.password_hash.clone());
+    let verified = verify_password(password, password_hash).await;
+    let Some(user) = user.filter(|_| verified) else {
+        return Err(AuthError::WrongCredentials);
+    };
+    if user.disabled {
+        return Err(AuthError::AccountDisabled);
+    }
+    Ok(user)
+}
+
+/// Exchange a refresh token for a new access token, rotating the refresh token
+pub async fn refresh_session(
+    pool: &SqlitePool,
+    jwt_keys: &JwtKeys,
+    refresh_token: &str,
+) -> Result<AuthBody, AuthError> {
+    let old_hash = hash_token(refresh_token);
+    let session = db::get_session_by_refresh_token(pool, &old_hash)
+        .await
+        .map_err(|_| AuthError::Database)?
+        .ok_or(AuthError::InvalidToken)?;
+
+    if session.revoked_at.is_some() {
+        return Err(AuthError::TokenRevoked);
+    }
+    if session.expires_at < Utc::now() {
+        return Err(AuthError::TokenExpired);
+    }
+
+    let user = db::get_user_by_id(pool, session.user_id)
+        .await
+        .map_err(|_| AuthError::Database)?
+        .ok_or(AuthError::InvalidToken)?;
+    let access_token = make_jwt_token(jwt_keys, &user, &session.id, session.second_factor)?;
 
-    let claims = Claims { iss, sub, exp };
-    let header = Header::new(Algorithm::HS512);
-    let token =
-        encode(&header, &claims, &jwt_keys.encoding).map_err(|_| AuthError::TokenCreation)?;
+    let new_refresh_token = random_token(32);
+    let expires_at = Utc::now() + REFRESH_TOKEN_TTL;
+    let rotated = db::rotate_session(
+        pool,
+        &session.id,
+        &old_hash,
+        &hash_token(&new_refresh_token),
+        expires_at,
+    )
+    .await
+    .map_err(|_| AuthError::Database)?;
 
-    Ok(AuthBody::new(token))
+    // Another request used this refresh token first
+    if !rotated {
+        return Err(AuthError::InvalidToken);
+    }
+
+    Ok(AuthBody::new(access_token, new_refresh_token))
+}
+
+/// Revoke the session an access token belongs to, along with the token itself
+pub async fn end_session(pool: &SqlitePool, claims: &Claims) -> Result<(), AuthError> {
+    db::revoke_session(pool, &claims.sid)
+        .await
+        .map_err(|_| AuthError::Database)?;
+    db::revoke_token(pool, &claims.jti, claims.expires_at())
+        .await
+        .map_err(|_| AuthError::Database)
+}
+
+/// Generate a new API key, returning the key to show the user once and its
+/// display prefix and hash for storage
+pub fn make_api_key() -> (String, String, String) {
+    let key = format!("qsk_{}", random_token(32));
+    let prefix = key[..12].to_string();
+    let key_hash = hash_token(&key);
+    (key, prefix, key_hash)
+}
+
+/// Resolve an `X-API-Key` header value to claims for its owner
+pub async fn authenticate_api_key(pool: &SqlitePool, key: &str) -> Result<Claims, AuthError> {
+    let api_key = db::get_api_key_by_hash(pool, &hash_token(key))
+        .await
+        .map_err(|_| AuthError::Database)?
+        .ok_or(AuthError::WrongCredentials)?;
+
+    if api_key.revoked_at.is_some() {
+        return Err(AuthError::TokenRevoked);
+    }
+    if api_key.expires_at.is_some_and(|exp| exp < Utc::now()) {
+        return Err(AuthError::TokenExpired);
+    }
+
+    let user = db::get_user_by_id(pool, api_key.user_id)
+        .await
+        .map_err(|_| AuthError::Database)?
+        .ok_or(AuthError::WrongCredentials)?;
+    if user.disabled {
+        return Err(AuthError::AccountDisabled);
+    }
+
+    db::touch_api_key(pool, api_key.id)
+        .await
+        .map_err(|_| AuthError::Database)?;
+
+    let exp = api_key.expires_at.map_or(u64::MAX, |exp| {
+        u64::try_from(exp.timestamp()).unwrap_or_default()
+    });
+
+    Ok(Claims {
+        iss: "quote-server.localhost".to_string(),
+        sub: user.id.to_string(),
+        exp,
+        jti: format!("api-key-{}", api_key.id),
+        sid: String::new(),
+        role: user.role,
+        api_key_id: Some(api_key.id),
+        scopes: Some(Scope::parse_list(&api_key.scopes)),
+        totp_enabled: user.totp_enabled(),
+        second_factor: false,
+    })
+}
+
+/// Read a cookie from the request headers
+pub fn cookie<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value)
+}
+
+/// CSRF token for the current request, for embedding in forms
+#[derive(Clone, Debug, Default)]
+pub struct CsrfToken(pub String);
+
+#[derive(Deserialize)]
+struct CsrfForm {
+    csrf_token: Option<String>,
+}
+
+/// Middleware issuing the CSRF cookie and checking it on state-changing requests.
+///
+/// Form posts must include the token in a `csrf_token` field. Other requests
+/// authenticated by the session cookie must send it in the `X-CSRF-Token` header;
+/// requests using a bearer token or API key are not affected.
+pub async fn csrf_protect(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let existing = cookie(request.headers(), CSRF_COOKIE).map(str::to_owned);
+    let token = existing.clone().unwrap_or_else(|| random_token(16));
+
+    let mut request = if matches!(
+        *request.method(),
+        Method::GET | Method::HEAD | Method::OPTIONS
+    ) {
+        request
+    } else {
+        match check_csrf(request, existing.as_deref()).await {
+            Ok(request) => request,
+            Err(e) => return e.into_response(),
+        }
+    };
+
+    request.extensions_mut().insert(CsrfToken(token.clone()));
+    let mut response = next.run(request).await;
+
+    if existing.is_none() {
+        let cookie = format!(
+            "{}={}; Path=/; {}",
+            CSRF_COOKIE,
+            token,
+            cookie_attributes(state.secure_cookies)
+        );
+        if let Ok(value) = HeaderValue::from_str(&cookie) {
+            response.headers_mut().append(header::SET_COOKIE, value);
+        }
+    }
+    response
+}
+
+async fn check_csrf(request: Request, expected: Option<&str>) -> Result<Request, AuthError> {
+    let is_form = request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/x-www-form-urlencoded"));
+
+    if is_form {
+        // Buffer the body to read the token field, then hand it on unchanged
+        let (parts, body) = request.into_parts();
+        let bytes = axum::body::to_bytes(body, 1024 * 1024)
+            .await
+            .map_err(|_| AuthError::InvalidCsrfToken)?;
+        let form_request = Request::builder()
+            .method(Method::POST)
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(Body::from(bytes.clone()))
+            .map_err(|_| AuthError::InvalidCsrfToken)?;
+        let Form(form) = Form::<CsrfForm>::from_request(form_request, &())
+            .await
+            .map_err(|_| AuthError::InvalidCsrfToken)?;
+
+        if expected.is_none() || form.csrf_token.as_deref() != expected {
+            return Err(AuthError::InvalidCsrfToken);
+        }
+        return Ok(Request::from_parts(parts, Body::from(bytes)));
+    }
+
+    let headers = request.headers();
+    let cookie_auth = cookie(headers, SESSION_COOKIE).is_some()
+        && !headers.contains_key(header::AUTHORIZATION)
+        && !headers.contains_key("x-api-key");
+    if cookie_auth {
+        let sent = headers
+            .get(CSRF_HEADER)
+            .and_then(|value| value.to_str().ok());
+        if expected.is_none() || sent != expected {
+            return Err(AuthError::InvalidCsrfToken);
+        }
+    }
+    Ok(request)
 }
 
 /// Validate JWT token and extract claims
 pub fn validate_token(jwt_keys: &JwtKeys, token: &str) -> Result<Claims, AuthError> {
-    let validation = Validation::new(Algorithm::HS512);
+    let header = decode_header(token).map_err(|_| AuthError::InvalidToken)?;
+    let key = jwt_keys
+        .verification_key(header.kid.as_deref())
+        .ok_or(AuthError::InvalidToken)?;
+    let validation = Validation::new(key.algorithm);
 
-    match decode::<Claims>(token, &jwt_keys.decoding, &validation) {
+    match decode::<Claims>(token, &key.decoding, &validation) {
         Ok(token_data) => {
             let now = Utc::now().timestamp() as u64;
             if token_data.claims.exp < now {
@@ -153,10 +1148,31 @@ where
     type Rejection = AuthError;
 
     async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        // API keys are accepted in place of a bearer token
+        if let Some(api_key) = parts.headers.get("x-api-key") {
+            let api_key = api_key.to_str().map_err(|_| AuthError::InvalidToken)?;
+            let pool = parts
+                .extensions
+                .get::<SqlitePool>()
+                .ok_or(AuthError::Database)?;
+            return authenticate_api_key(pool, api_key).await;
+        }
+
+        // The web UI's session cookie is used when there is no bearer token
+        if !parts.headers.contains_key(header::AUTHORIZATION)
+            && let Some(token) = cookie(&parts.headers, SESSION_COOKIE)
+        {
+            let pool = parts
+                .extensions
+                .get::<SqlitePool>()
+                .ok_or(AuthError::Database)?;
+            return authenticate_web_session(pool, token).await;
+        }
+
         // Extract Authorization header
         let authorization = parts
             .headers
-            .get(axum::http::header::AUTHORIZATION)
+            .get(header::AUTHORIZATION)
             .and_then(|header| header.to_str().ok())
             .ok_or(AuthError::MissingCredentials)?;
 
@@ -171,7 +1187,90 @@ where
             .get::<JwtKeys>()
             .ok_or(AuthError::InvalidToken)?;
 
-        // Validate token and return claims
-        validate_token(jwt_keys, token)
+        // Validate token
+        let mut claims = validate_token(jwt_keys, token)?;
+
+        // Reject tokens that were revoked before they expired
+        let pool = parts
+            .extensions
+            .get::<SqlitePool>()
+            .ok_or(AuthError::Database)?;
+        match db::is_token_revoked(pool, &claims.jti, &claims.sid).await {
+            Ok(false) => {}
+            Ok(true) => return Err(AuthError::TokenRevoked),
+            Err(_) => return Err(AuthError::Database),
+        }
+
+        // Make sure the account still exists and has not been disabled
+        match db::get_user_by_id(pool, claims.user_id()?).await {
+            Ok(Some(user)) if !user.disabled => {
+                // A demotion takes effect immediately, a promotion on next login
+                claims.role = claims.role.min(user.role);
+                claims.totp_enabled = user.totp_enabled();
+                Ok(claims)
+            }
+            Ok(Some(_)) => Err(AuthError::AccountDisabled),
+            Ok(None) => Err(AuthError::InvalidToken),
+            Err(_) => Err(AuthError::Database),
+        }
+    }
+}
+
+/// Minimum role, and the API key scope, required by a [`RequireRole`] guard
+pub trait MinimumRole {
+    const ROLE: Role;
+    const SCOPE: Scope;
+}
+
+/// Marker for endpoints open to contributors and above
+pub struct Contributor;
+
+impl MinimumRole for Contributor {
+    const ROLE: Role = Role::Contributor;
+    const SCOPE: Scope = Scope::QuotesCreate;
+}
+
+/// Marker for endpoints open to editors and above, such as changes that reach
+/// every quote
+pub struct Editor;
+
+impl MinimumRole for Editor {
+    const ROLE: Role = Role::Editor;
+    const SCOPE: Scope = Scope::QuotesEdit;
+}
+
+/// Marker for endpoints open to admins only
+pub struct Admin;
+
+impl MinimumRole for Admin {
+    const ROLE: Role = Role::Admin;
+    const SCOPE: Scope = Scope::Admin;
+}
+
+/// Axum extractor that authenticates the request and rejects it with
+/// 403 Forbidden unless the user holds at least the role `R` and, for
+/// API keys, the key carries the matching scope
+pub struct RequireRole<R> {
+    pub claims: Claims,
+    role: PhantomData<R>,
+}
+
+impl<S, R> FromRequestParts<S> for RequireRole<R>
+where
+    S: Send + Sync,
+    R: MinimumRole,
+{
+    type Rejection = AuthError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let claims = Claims::from_request_parts(parts, state).await?;
+        if !claims.has_role(R::ROLE) || !claims.has_scope(R::SCOPE) {
+            return Err(AuthError::Forbidden);
+        }
+
+        Ok(Self {
+            claims,
+            role: PhantomData,
+        })
     }
 }
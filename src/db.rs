@@ -1,12 +1,26 @@
 //! Database interaction module for the Quotes Server.
 //!
 //! Provides functions for SQLite database initialization, migration handling,
-//! importing default quotes from CSV, and CRUD operations for quotes and tags.
+//! importing default quotes from CSV, CRUD operations and full-text search for
+//! quotes and tags, quote revision history and the trash of deleted quotes, and
+//! storage for user accounts, login sessions, revoked tokens, API keys, linked
+//! OpenID Connect identities, the login attempt audit log, and TOTP secrets,
+//! recovery codes and pending two-step logins.
 //!
+use crate::api::{QuoteSort, SearchParams, SortOrder};
+use crate::authjwt::{Role, Scope};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use sqlx::{Pool, Sqlite, migrate::MigrateDatabase, sqlite::SqlitePoolOptions};
-use std::{collections::HashSet, fs, path::Path};
+use sqlx::{
+    Pool, QueryBuilder, Sqlite, Transaction, migrate::MigrateDatabase, sqlite::SqlitePoolOptions,
+    types::Json,
+};
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, BTreeSet, HashSet},
+    fs,
+    path::Path,
+};
 use tracing::info;
 use utoipa::ToSchema;
 
@@ -18,24 +32,42 @@ pub struct QuoteFromCsv {
     pub quote: String,
     pub source: String,
     pub tags: String,
+    pub work: Option<String>,
+    pub year: Option<i64>,
+    pub page: Option<String>,
+    pub url: Option<String>,
+    pub note: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct Quote {
-    pub id: i64,
-    pub quote: String,
-    pub source: String,
-    pub created_at: DateTime<Utc>,
-    pub updated_at: DateTime<Utc>,
+/// Where a quote comes from, beyond who said it
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct Citation {
+    /// Title of the work, such as a book, speech or film, the quote appears in
+    #[schema(example = "Stanford commencement address")]
+    pub work: Option<String>,
+    /// Year the work was published or the words were spoken
+    #[schema(example = 2005)]
+    pub year: Option<i64>,
+    /// Page, chapter or other location within the work
+    #[schema(example = "p. 12")]
+    pub page: Option<String>,
+    /// Link to the text or a recording of the quote
+    #[schema(example = "https://news.stanford.edu/2005/06/12/youve-got-find-love-jobs-says/")]
+    pub url: Option<String>,
+    /// Free-form remarks, such as a translation or doubts about the attribution
+    #[schema(example = "Delivered on June 12, 2005")]
+    pub note: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct Tag {
-    pub id: i64,
-    pub quote_id: i64,
-    pub name: String,
-    pub created_at: DateTime<Utc>,
-    pub updated_at: DateTime<Utc>,
+impl Citation {
+    /// Year and location within the work, in the order they are cited
+    pub fn details(&self) -> Vec<String> {
+        self.year
+            .map(|year| year.to_string())
+            .into_iter()
+            .chain(self.page.clone())
+            .collect()
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, ToSchema)]
@@ -49,17 +81,366 @@ pub struct QuoteWithTags {
     /// Source or author of the quote
     #[schema(example = "Steve Jobs")]
     pub source: String,
+    /// ID of the author the source names, if known
+    #[schema(example = 1)]
+    pub author_id: Option<i64>,
+    /// Where the quote comes from
+    #[serde(flatten)]
+    pub citation: Citation,
     /// Timestamp when the quote was created
     #[schema(value_type = String, format = DateTime, example = "2024-01-01T12:00:00Z")]
     pub created_at: DateTime<Utc>,
     /// Timestamp when the quote was last updated
     #[schema(value_type = String, format = DateTime, example = "2024-01-01T12:00:00Z")]
     pub updated_at: DateTime<Utc>,
+    /// ID of the user who created the quote, if known
+    #[schema(example = 1)]
+    pub created_by: Option<i64>,
+    /// ID of the user who last updated the quote, if known
+    #[schema(example = 1)]
+    pub updated_by: Option<i64>,
+    /// Version of the quote, incremented by every change to it and sent as its `ETag`
+    #[schema(example = 1)]
+    pub version: i64,
     /// List of tags associated with the quote
     #[schema(example = json!(["motivation", "work", "success"]))]
     pub tags: Vec<String>,
+    /// Excerpt around the words matching a full-text search (`q`), HTML-escaped
+    /// with the matches wrapped in `<mark>`; only present in search results
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(example = "The only way to do great <mark>work</mark> is to love what you do.")]
+    pub snippet: Option<String>,
+    /// Timestamp when the quote was moved to the trash; only present for quotes in the trash
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<String>, format = DateTime, example = "2024-06-01T08:00:00Z")]
+    pub deleted_at: Option<DateTime<Utc>>,
+}
+
+/// One page of a quote listing
+#[derive(Debug)]
+pub struct QuotePage {
+    pub quotes: Vec<QuoteWithTags>,
+    /// Number of quotes matching the search across all pages
+    pub total: i64,
+}
+
+/// What produced a quote revision
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(rename_all = "lowercase")]
+pub enum RevisionAction {
+    /// The quote was added
+    Create,
+    /// The quote was edited
+    Update,
+    /// An earlier revision was restored
+    Restore,
+    /// A tag the quote carries was renamed or merged into another
+    Tag,
+    /// The quote was moved to the trash
+    Delete,
+    /// The quote was taken back out of the trash
+    Undelete,
+}
+
+/// The state of a quote after one change
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct QuoteRevision {
+    /// ID of the quote the revision belongs to
+    #[schema(example = 1)]
+    pub quote_id: i64,
+    /// Number of the revision, counting from 1 for each quote
+    #[schema(example = 2)]
+    pub revision: i64,
+    /// What produced the revision
+    #[schema(example = "update")]
+    pub action: RevisionAction,
+    /// Revision that was restored, for `restore` revisions
+    #[schema(example = json!(null))]
+    pub restored_from: Option<i64>,
+    /// The quote text
+    #[schema(example = "The only way to do great work is to love what you do.")]
+    pub quote: String,
+    /// Source or author of the quote
+    #[schema(example = "Steve Jobs")]
+    pub source: String,
+    /// ID of the author the source named
+    #[schema(example = 1)]
+    pub author_id: Option<i64>,
+    /// Where the quote comes from
+    #[serde(flatten)]
+    pub citation: Citation,
+    /// Tags the quote carried
+    #[schema(example = json!(["motivation", "work"]))]
+    pub tags: Vec<String>,
+    /// ID of the user who made the change, if known
+    #[schema(example = 1)]
+    pub user_id: Option<i64>,
+    /// Timestamp of the change
+    #[schema(value_type = String, format = DateTime, example = "2024-01-01T12:00:00Z")]
+    pub created_at: DateTime<Utc>,
+}
+
+/// A revision as loaded by [`select_revisions`]
+#[derive(sqlx::FromRow)]
+struct RevisionRow {
+    quote_id: i64,
+    revision: i64,
+    action: RevisionAction,
+    restored_from: Option<i64>,
+    quote: String,
+    source: String,
+    author_id: Option<i64>,
+    work: Option<String>,
+    year: Option<i64>,
+    page: Option<String>,
+    url: Option<String>,
+    note: Option<String>,
+    /// Tag names in alphabetical order
+    tags: Json<Vec<String>>,
+    user_id: Option<i64>,
+    created_at: DateTime<Utc>,
+}
+
+impl From<RevisionRow> for QuoteRevision {
+    fn from(row: RevisionRow) -> Self {
+        Self {
+            quote_id: row.quote_id,
+            revision: row.revision,
+            action: row.action,
+            restored_from: row.restored_from,
+            quote: row.quote,
+            source: row.source,
+            author_id: row.author_id,
+            citation: Citation {
+                work: row.work,
+                year: row.year,
+                page: row.page,
+                url: row.url,
+                note: row.note,
+            },
+            tags: row.tags.0,
+            user_id: row.user_id,
+            created_at: row.created_at,
+        }
+    }
+}
+
+/// Quote fields compared between revisions, in the order they appear on a quote
+const REVISION_FIELDS: [&str; 8] = [
+    "quote",
+    "source",
+    "author_id",
+    "work",
+    "year",
+    "page",
+    "url",
+    "note",
+];
+
+impl QuoteRevision {
+    /// Changes made between this revision and a later one
+    pub fn diff(&self, to: &QuoteRevision) -> RevisionDiff {
+        let before = serde_json::to_value(self).unwrap_or_default();
+        let after = serde_json::to_value(to).unwrap_or_default();
+        let changes = REVISION_FIELDS
+            .iter()
+            .filter(|field| before[**field] != after[**field])
+            .map(|field| FieldChange {
+                field: field.to_string(),
+                before: before[*field].clone(),
+                after: after[*field].clone(),
+            })
+            .collect();
+
+        let tags_before: BTreeSet<&String> = self.tags.iter().collect();
+        let tags_after: BTreeSet<&String> = to.tags.iter().collect();
+        RevisionDiff {
+            quote_id: self.quote_id,
+            from: self.revision,
+            to: to.revision,
+            changes,
+            tags_added: tags_after
+                .difference(&tags_before)
+                .map(|tag| tag.to_string())
+                .collect(),
+            tags_removed: tags_before
+                .difference(&tags_after)
+                .map(|tag| tag.to_string())
+                .collect(),
+        }
+    }
+}
+
+/// Differences between two revisions of a quote
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct RevisionDiff {
+    /// ID of the quote the revisions belong to
+    #[schema(example = 1)]
+    pub quote_id: i64,
+    /// Revision compared from
+    #[schema(example = 1)]
+    pub from: i64,
+    /// Revision compared to
+    #[schema(example = 2)]
+    pub to: i64,
+    /// Fields whose values differ, tags aside
+    pub changes: Vec<FieldChange>,
+    /// Tags only the later revision carries
+    #[schema(example = json!(["work"]))]
+    pub tags_added: Vec<String>,
+    /// Tags only the earlier revision carries
+    #[schema(example = json!(["jobs"]))]
+    pub tags_removed: Vec<String>,
+}
+
+/// A quote field that differs between two revisions
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct FieldChange {
+    /// Name of the field
+    #[schema(example = "source")]
+    pub field: String,
+    /// Value in the earlier revision
+    #[schema(example = "Jobs")]
+    pub before: serde_json::Value,
+    /// Value in the later revision
+    #[schema(example = "Steve Jobs")]
+    pub after: serde_json::Value,
+}
+
+/// A tag and the number of quotes carrying it
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct TagWithCount {
+    /// Unique identifier for the tag
+    #[schema(example = 1)]
+    pub id: i64,
+    /// The tag name
+    #[schema(example = "inspiration")]
+    pub name: String,
+    /// Number of quotes carrying the tag
+    #[schema(example = 12)]
+    pub quote_count: i64,
+}
+
+/// A person quotes are attributed to
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct Author {
+    /// Unique identifier for the author
+    #[schema(example = 1)]
+    pub id: i64,
+    /// Full name of the author
+    #[schema(example = "Albert Einstein")]
+    pub name: String,
+    /// Other spellings of the name that quote sources are matched against
+    #[schema(example = json!(["Einstein", "A. Einstein"]))]
+    pub aliases: Vec<String>,
+    #[schema(example = 1879)]
+    pub birth_year: Option<i64>,
+    #[schema(example = 1955)]
+    pub death_year: Option<i64>,
+    /// Short biography
+    #[schema(example = "Theoretical physicist who developed the theory of relativity.")]
+    pub bio: Option<String>,
+    /// Links to further information about the author
+    #[schema(example = json!(["https://en.wikipedia.org/wiki/Albert_Einstein"]))]
+    pub links: Vec<String>,
+    /// Number of quotes linked to the author
+    #[schema(example = 12)]
+    pub quote_count: i64,
+    #[schema(value_type = String, format = DateTime, example = "2024-01-01T12:00:00Z")]
+    pub created_at: DateTime<Utc>,
+    #[schema(value_type = String, format = DateTime, example = "2024-01-01T12:00:00Z")]
+    pub updated_at: DateTime<Utc>,
+}
+
+/// An author as loaded by [`select_authors`]
+#[derive(sqlx::FromRow)]
+struct AuthorRow {
+    id: i64,
+    name: String,
+    /// Aliases in alphabetical order
+    aliases: Json<Vec<String>>,
+    birth_year: Option<i64>,
+    death_year: Option<i64>,
+    bio: Option<String>,
+    links: Json<Vec<String>>,
+    quote_count: i64,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
 }
 
+impl From<AuthorRow> for Author {
+    fn from(row: AuthorRow) -> Self {
+        Self {
+            id: row.id,
+            name: row.name,
+            aliases: row.aliases.0,
+            birth_year: row.birth_year,
+            death_year: row.death_year,
+            bio: row.bio,
+            links: row.links.0,
+            quote_count: row.quote_count,
+            created_at: row.created_at,
+            updated_at: row.updated_at,
+        }
+    }
+}
+
+/// A quote as loaded by [`select_quotes`], with its tags
+#[derive(sqlx::FromRow)]
+struct QuoteRow {
+    id: i64,
+    quote: String,
+    source: String,
+    author_id: Option<i64>,
+    work: Option<String>,
+    year: Option<i64>,
+    page: Option<String>,
+    url: Option<String>,
+    note: Option<String>,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+    created_by: Option<i64>,
+    updated_by: Option<i64>,
+    version: i64,
+    /// Tag names in alphabetical order
+    tags: Json<Vec<String>>,
+    /// Raw snippet for full-text searches, with matches delimited by
+    /// [`MATCH_START`] and [`MATCH_END`]
+    snippet: Option<String>,
+    deleted_at: Option<DateTime<Utc>>,
+}
+
+impl From<QuoteRow> for QuoteWithTags {
+    fn from(row: QuoteRow) -> Self {
+        Self {
+            id: row.id,
+            quote: row.quote,
+            source: row.source,
+            author_id: row.author_id,
+            citation: Citation {
+                work: row.work,
+                year: row.year,
+                page: row.page,
+                url: row.url,
+                note: row.note,
+            },
+            created_at: row.created_at,
+            updated_at: row.updated_at,
+            created_by: row.created_by,
+            updated_by: row.updated_by,
+            version: row.version,
+            tags: row.tags.0,
+            snippet: row.snippet.as_deref().map(highlight),
+            deleted_at: row.deleted_at,
+        }
+    }
+}
+
+/// Markers FTS5 places around matches in snippets, replaced once the text is escaped
+const MATCH_START: char = '\u{2}';
+const MATCH_END: char = '\u{3}';
+
 #[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct CreateQuoteRequest {
     /// The quote text
@@ -68,6 +449,14 @@ pub struct CreateQuoteRequest {
     /// Source or author of the quote
     #[schema(example = "Eleanor Roosevelt")]
     pub source: String,
+    /// Author to link the quote to; by default the author named by `source`
+    /// is found or created
+    #[serde(default)]
+    #[schema(example = 1)]
+    pub author_id: Option<i64>,
+    /// Where the quote comes from
+    #[serde(flatten)]
+    pub citation: Citation,
     /// Optional list of tags for the quote
     #[schema(example = json!(["dreams", "future", "motivation"]))]
     pub tags: Option<Vec<String>>,
@@ -81,11 +470,354 @@ pub struct UpdateQuoteRequest {
     /// Updated source or author of the quote
     #[schema(example = "Eleanor Roosevelt")]
     pub source: String,
+    /// Author to link the quote to; by default the author named by `source`
+    /// is found or created
+    #[serde(default)]
+    #[schema(example = 1)]
+    pub author_id: Option<i64>,
+    /// Updated citation for the quote (replaces the existing one)
+    #[serde(flatten)]
+    pub citation: Citation,
     /// Updated list of tags for the quote (replaces existing tags)
     #[schema(example = json!(["dreams", "future", "motivation", "inspiration"]))]
     pub tags: Option<Vec<String>>,
 }
 
+/// JSON Merge Patch (RFC 7396) for a quote. Fields left out keep their value,
+/// and `null` clears a citation field.
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct PatchQuoteRequest {
+    /// New quote text
+    #[serde(default, deserialize_with = "nullable")]
+    #[schema(value_type = Option<String>, example = "The future belongs to those who believe in their dreams.")]
+    pub quote: Option<Option<String>>,
+    /// New source or author of the quote
+    #[serde(default, deserialize_with = "nullable")]
+    #[schema(value_type = Option<String>, example = "Eleanor Roosevelt")]
+    pub source: Option<Option<String>>,
+    /// Author to link the quote to; `null` finds or creates the author named by
+    /// `source`, as does changing `source` without giving an author
+    #[serde(default, deserialize_with = "nullable")]
+    #[schema(value_type = Option<i64>, example = 1)]
+    pub author_id: Option<Option<i64>>,
+    /// Title of the work the quote appears in
+    #[serde(default, deserialize_with = "nullable")]
+    #[schema(value_type = Option<String>, example = "Stanford commencement address")]
+    pub work: Option<Option<String>>,
+    /// Year the work was published or the words were spoken
+    #[serde(default, deserialize_with = "nullable")]
+    #[schema(value_type = Option<i64>, example = 2005)]
+    pub year: Option<Option<i64>>,
+    /// Page, chapter or other location within the work
+    #[serde(default, deserialize_with = "nullable")]
+    #[schema(value_type = Option<String>, example = "p. 12")]
+    pub page: Option<Option<String>>,
+    /// Link to the text or a recording of the quote
+    #[serde(default, deserialize_with = "nullable")]
+    #[schema(value_type = Option<String>, example = "https://example.com/speech")]
+    pub url: Option<Option<String>>,
+    /// Free-form remarks about the quote
+    #[serde(default, deserialize_with = "nullable")]
+    #[schema(value_type = Option<String>, example = "Delivered on June 12, 2005")]
+    pub note: Option<Option<String>>,
+    /// Changes to the quote's tags: a list replaces them all, `null` removes
+    /// them all, and an object adds the tags set to `true` and removes those
+    /// set to `null` or `false`
+    #[serde(default, deserialize_with = "nullable")]
+    #[schema(value_type = Option<TagsPatch>, example = json!({"inspiration": true, "motivation": null}))]
+    pub tags: Option<Option<TagsPatch>>,
+}
+
+/// Changes to a quote's tags in a [`PatchQuoteRequest`]
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(untagged)]
+pub enum TagsPatch {
+    /// Replace the tags with these
+    Replace(Vec<String>),
+    /// Add the tags set to `true`, and remove those set to `null` or `false`
+    Edit(BTreeMap<String, Option<bool>>),
+}
+
+impl PatchQuoteRequest {
+    /// Full update making the patch's changes to `quote`
+    pub fn apply_to(self, quote: &QuoteWithTags) -> UpdateQuoteRequest {
+        let source = match self.source {
+            Some(source) => source.unwrap_or_default(),
+            None => quote.source.clone(),
+        };
+        // A new source is linked to its own author unless one is given
+        let author_id = match self.author_id {
+            Some(author_id) => author_id,
+            None if source != quote.source => None,
+            None => quote.author_id,
+        };
+        let patch = |field: Option<Option<String>>, current: &Option<String>| {
+            field.unwrap_or_else(|| current.clone())
+        };
+        let citation = Citation {
+            work: patch(self.work, &quote.citation.work),
+            year: self.year.unwrap_or(quote.citation.year),
+            page: patch(self.page, &quote.citation.page),
+            url: patch(self.url, &quote.citation.url),
+            note: patch(self.note, &quote.citation.note),
+        };
+        let tags = match self.tags {
+            None => quote.tags.clone(),
+            Some(None) => Vec::new(),
+            Some(Some(TagsPatch::Replace(tags))) => tags,
+            Some(Some(TagsPatch::Edit(changes))) => {
+                let mut tags: BTreeSet<String> = quote.tags.iter().cloned().collect();
+                for (tag, keep) in changes {
+                    let tag = tag.trim().to_string();
+                    if keep.unwrap_or(false) {
+                        tags.insert(tag);
+                    } else {
+                        tags.remove(&tag);
+                    }
+                }
+                tags.into_iter().collect()
+            }
+        };
+
+        UpdateQuoteRequest {
+            quote: match self.quote {
+                Some(text) => text.unwrap_or_default(),
+                None => quote.quote.clone(),
+            },
+            source,
+            author_id,
+            citation,
+            tags: Some(tags),
+        }
+    }
+}
+
+/// Read a merge patch field, telling a `null` value (`Some(None)`) apart from
+/// a missing one (`None`, with `#[serde(default)]`)
+fn nullable<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+#[derive(Debug)]
+pub struct User {
+    pub id: i64,
+    pub full_name: String,
+    pub email: String,
+    pub password_hash: String,
+    pub role: Role,
+    pub disabled: bool,
+    pub created_at: DateTime<Utc>,
+    pub totp_enabled_at: Option<DateTime<Utc>>,
+}
+
+impl User {
+    /// Whether logging in needs a TOTP code after the password
+    pub fn totp_enabled(&self) -> bool {
+        self.totp_enabled_at.is_some()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct UserProfile {
+    /// Unique identifier for the user
+    #[schema(example = 1)]
+    pub id: i64,
+    /// Full name of the user
+    #[schema(example = "Ada Lovelace")]
+    pub full_name: String,
+    /// Email address used to log in
+    #[schema(example = "ada@example.com")]
+    pub email: String,
+    /// Role granted to the user
+    #[schema(example = "contributor")]
+    pub role: Role,
+    /// Whether the account has been disabled
+    #[schema(example = false)]
+    pub disabled: bool,
+    /// Whether logging in needs a TOTP code
+    #[schema(example = false)]
+    pub totp_enabled: bool,
+    /// Timestamp when the account was created
+    #[schema(value_type = String, format = DateTime, example = "2024-01-01T12:00:00Z")]
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<User> for UserProfile {
+    fn from(user: User) -> Self {
+        let totp_enabled = user.totp_enabled();
+        Self {
+            id: user.id,
+            full_name: user.full_name,
+            email: user.email,
+            role: user.role,
+            disabled: user.disabled,
+            totp_enabled,
+            created_at: user.created_at,
+        }
+    }
+}
+
+/// How a session's token is presented: as a refresh token to the API, or as
+/// the web UI's session cookie
+#[derive(Debug, Clone, Copy, PartialEq, Eq, sqlx::Type)]
+#[sqlx(rename_all = "lowercase")]
+pub enum SessionKind {
+    Api,
+    Web,
+}
+
+#[derive(Debug)]
+pub struct Session {
+    pub id: String,
+    pub user_id: i64,
+    pub expires_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    /// Whether the login that started the session passed a TOTP code
+    pub second_factor: bool,
+}
+
+/// Login waiting for its second factor
+#[derive(Debug)]
+pub struct LoginChallenge {
+    pub user_id: i64,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Failed attempts counted towards a lockout
+#[derive(Debug)]
+pub struct AttemptStats {
+    pub failures: i64,
+    pub last_failure: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug)]
+pub struct ApiKey {
+    pub id: i64,
+    pub user_id: i64,
+    pub name: String,
+    pub prefix: String,
+    pub scopes: String,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ApiKeyInfo {
+    /// Unique identifier for the API key
+    #[schema(example = 1)]
+    pub id: i64,
+    /// Name describing what the key is used for
+    #[schema(example = "Daily quote bot")]
+    pub name: String,
+    /// First characters of the key, for recognizing it
+    #[schema(example = "qsk_1a2b3c4d")]
+    pub prefix: String,
+    /// Permissions granted to the key
+    #[schema(example = json!(["quotes:create"]))]
+    pub scopes: Vec<Scope>,
+    /// Timestamp after which the key stops working, if any
+    #[schema(value_type = Option<String>, format = DateTime, example = "2025-01-01T00:00:00Z")]
+    pub expires_at: Option<DateTime<Utc>>,
+    /// Timestamp when the key was last used
+    #[schema(value_type = Option<String>, format = DateTime, example = "2024-06-01T08:00:00Z")]
+    pub last_used_at: Option<DateTime<Utc>>,
+    /// Timestamp when the key was revoked, if it has been
+    #[schema(value_type = Option<String>, format = DateTime, example = json!(null))]
+    pub revoked_at: Option<DateTime<Utc>>,
+    /// Timestamp when the key was created
+    #[schema(value_type = String, format = DateTime, example = "2024-01-01T12:00:00Z")]
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<ApiKey> for ApiKeyInfo {
+    fn from(api_key: ApiKey) -> Self {
+        Self {
+            id: api_key.id,
+            name: api_key.name,
+            prefix: api_key.prefix,
+            scopes: Scope::parse_list(&api_key.scopes),
+            expires_at: api_key.expires_at,
+            last_used_at: api_key.last_used_at,
+            revoked_at: api_key.revoked_at,
+            created_at: api_key.created_at,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct CreateApiKeyRequest {
+    /// Name describing what the key is used for
+    #[schema(example = "Daily quote bot")]
+    pub name: String,
+    /// Permissions to grant; cannot exceed the creating user's role
+    #[schema(example = json!(["quotes:create"]))]
+    pub scopes: Vec<Scope>,
+    /// Optional timestamp after which the key stops working
+    #[schema(value_type = Option<String>, format = DateTime, example = "2025-01-01T00:00:00Z")]
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct CreatedApiKey {
+    /// The API key itself; it is only shown once
+    #[schema(example = "qsk_1a2b3c4d...")]
+    pub key: String,
+    #[serde(flatten)]
+    pub info: ApiKeyInfo,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct AuthorRequest {
+    /// Full name of the author
+    #[schema(example = "Albert Einstein")]
+    pub name: String,
+    /// Other spellings of the name that quote sources are matched against
+    #[serde(default)]
+    #[schema(example = json!(["Einstein", "A. Einstein"]))]
+    pub aliases: Vec<String>,
+    #[schema(example = 1879)]
+    pub birth_year: Option<i64>,
+    #[schema(example = 1955)]
+    pub death_year: Option<i64>,
+    /// Short biography
+    #[schema(example = "Theoretical physicist who developed the theory of relativity.")]
+    pub bio: Option<String>,
+    /// Links to further information about the author
+    #[serde(default)]
+    #[schema(example = json!(["https://en.wikipedia.org/wiki/Albert_Einstein"]))]
+    pub links: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct RenameTagRequest {
+    /// New name for the tag
+    #[schema(example = "inspiration")]
+    pub name: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct MergeTagRequest {
+    /// ID of the tag to move the quotes to
+    #[schema(example = 2)]
+    pub into: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct UpdateUserRequest {
+    /// New role for the user
+    #[schema(example = "editor")]
+    pub role: Option<Role>,
+    /// Disable or re-enable the account
+    #[schema(example = false)]
+    pub disabled: Option<bool>,
+}
+
 pub async fn init_db() -> Result<Pool<Sqlite>, sqlx::Error> {
     // Create db directory if it doesn't exist
     let db_dir = Path::new("db");
@@ -119,6 +851,12 @@ pub async fn init_db() -> Result<Pool<Sqlite>, sqlx::Error> {
         import_quotes_from_csv(&pool).await?;
     }
 
+    // Link imported quotes, and those from before authors were tracked, to authors
+    let linked = link_unattributed_quotes(&pool).await?;
+    if linked > 0 {
+        info!("Linked {linked} quotes to their authors.");
+    }
+
     Ok(pool)
 }
 
@@ -136,420 +874,2010 @@ async fn import_quotes_from_csv(pool: &Pool<Sqlite>) -> Result<(), sqlx::Error>
         quotes.push(record);
     }
 
-    // Process each quote individually
+    // Import every quote or, if one fails, none of them
+    let mut tx = pool.begin().await?;
     for quote in quotes {
         let now = Utc::now();
 
         // Insert quote
         let quote_id = sqlx::query!(
-            "INSERT INTO quotes (quote, source, created_at, updated_at) VALUES (?, ?, ?, ?)",
+            "INSERT INTO quotes (quote, source, work, year, page, url, note, created_at, updated_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
             quote.quote,
             quote.source,
+            quote.work,
+            quote.year,
+            quote.page,
+            quote.url,
+            quote.note,
             now,
             now
         )
-        .execute(pool)
+        .execute(&mut *tx)
         .await?
         .last_insert_rowid();
 
-        // Process tags
-        if !quote.tags.is_empty() {
-            // Split tags by comma and trim spaces
-            let tags: HashSet<String> = quote
-                .tags
-                .split(',')
-                .map(|s| s.trim().to_string())
-                .filter(|s| !s.is_empty())
-                .collect();
-
-            // Insert tags
-            for tag in tags {
-                // Avoid SQL injection by using parameterized query
-                let _ = sqlx::query!(
-                    "INSERT INTO tags (quote_id, name, created_at, updated_at) VALUES (?, ?, ?, ?)",
-                    quote_id,
-                    tag,
-                    now,
-                    now
-                )
-                .execute(pool)
-                .await?;
-            }
-        }
+        // Split tags by comma
+        set_quote_tags(
+            &mut tx,
+            quote_id,
+            quote.tags.split(',').map(str::to_string),
+            now,
+        )
+        .await?;
+        record_revision(&mut tx, quote_id, RevisionAction::Create, None, None, now).await?;
     }
+    tx.commit().await?;
 
     info!("Successfully imported quotes from CSV.");
     Ok(())
 }
 
-// Function to create a new quote
+// Function to create a new quote, along with its author, tags and first
+// revision as a single transaction
 pub async fn create_quote(
     pool: &Pool<Sqlite>,
     request: CreateQuoteRequest,
+    user_id: i64,
 ) -> Result<QuoteWithTags, sqlx::Error> {
     let now = Utc::now();
+    let mut tx = pool.begin().await?;
+    let author_id = match request.author_id {
+        Some(author_id) => author_id,
+        None => find_or_create_author(&mut tx, &request.source, now).await?,
+    };
 
     // Insert the quote
+    let citation = &request.citation;
     let quote_id = sqlx::query!(
-        "INSERT INTO quotes (quote, source, created_at, updated_at) VALUES (?, ?, ?, ?)",
+        "INSERT INTO quotes (quote, source, author_id, work, year, page, url, note, created_at, updated_at, created_by, updated_by) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
         request.quote,
         request.source,
+        author_id,
+        citation.work,
+        citation.year,
+        citation.page,
+        citation.url,
+        citation.note,
         now,
-        now
+        now,
+        user_id,
+        user_id
     )
-    .execute(pool)
+    .execute(&mut *tx)
     .await?
     .last_insert_rowid();
 
-    // Insert tags if provided
-    let mut tag_names = Vec::new();
-    if let Some(tags) = request.tags {
-        // Use HashSet to remove duplicates and filter empty strings
-        let unique_tags: HashSet<String> = tags
-            .into_iter()
-            .map(|s| s.trim().to_string())
-            .filter(|s| !s.is_empty())
-            .collect();
-
-        for tag in &unique_tags {
-            sqlx::query!(
-                "INSERT INTO tags (quote_id, name, created_at, updated_at) VALUES (?, ?, ?, ?)",
-                quote_id,
-                tag,
-                now,
-                now
-            )
-            .execute(pool)
-            .await?;
-        }
-
-        tag_names = unique_tags.into_iter().collect();
-        tag_names.sort(); // Sort for consistent ordering
-    }
+    // Link tags if provided
+    let tag_names =
+        set_quote_tags(&mut tx, quote_id, request.tags.unwrap_or_default(), now).await?;
+    record_revision(
+        &mut tx,
+        quote_id,
+        RevisionAction::Create,
+        None,
+        Some(user_id),
+        now,
+    )
+    .await?;
+    tx.commit().await?;
 
     // Return the created quote with tags
     Ok(QuoteWithTags {
         id: quote_id,
         quote: request.quote,
         source: request.source,
+        author_id: Some(author_id),
+        citation: request.citation,
         created_at: now,
         updated_at: now,
+        created_by: Some(user_id),
+        updated_by: Some(user_id),
+        version: 1,
         tags: tag_names,
+        snippet: None,
+        deleted_at: None,
     })
 }
 
-// Function to update an existing quote
+// Function to update an existing quote. With an `expected_version`, the quote
+// is only updated if it is still at that version.
 pub async fn update_quote(
     pool: &Pool<Sqlite>,
     quote_id: i64,
     request: UpdateQuoteRequest,
+    user_id: i64,
+    expected_version: Option<i64>,
+) -> Result<Option<QuoteWithTags>, sqlx::Error> {
+    apply_quote_update(
+        pool,
+        quote_id,
+        request,
+        user_id,
+        expected_version,
+        RevisionAction::Update,
+        None,
+    )
+    .await
+}
+
+// Function to replace a quote's fields and tags, recording the result as a new
+// revision produced by `action`, as a single transaction. Returns `None` if the
+// quote does not exist or is no longer at `expected_version`.
+async fn apply_quote_update(
+    pool: &Pool<Sqlite>,
+    quote_id: i64,
+    request: UpdateQuoteRequest,
+    user_id: i64,
+    expected_version: Option<i64>,
+    action: RevisionAction,
+    restored_from: Option<i64>,
+) -> Result<Option<QuoteWithTags>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let quote = update_quote_in(
+        &mut tx,
+        quote_id,
+        request,
+        user_id,
+        expected_version,
+        action,
+        restored_from,
+    )
+    .await?;
+    if quote.is_some() {
+        tx.commit().await?;
+    }
+    Ok(quote)
+}
+
+// Function to update a quote as part of a larger transaction, see `apply_quote_update`
+async fn update_quote_in(
+    tx: &mut Transaction<'_, Sqlite>,
+    quote_id: i64,
+    request: UpdateQuoteRequest,
+    user_id: i64,
+    expected_version: Option<i64>,
+    action: RevisionAction,
+    restored_from: Option<i64>,
 ) -> Result<Option<QuoteWithTags>, sqlx::Error> {
     let now = Utc::now();
 
-    // First, check if the quote exists and get its creation timestamp
+    // First, check if the quote exists and get its creation timestamp, author and version
     let existing_quote = sqlx::query!(
-        "SELECT created_at as \"created_at: DateTime<Utc>\" FROM quotes WHERE id = ?",
+        "SELECT created_at as \"created_at: DateTime<Utc>\", created_by, version FROM quotes WHERE id = ? AND deleted_at IS NULL",
         quote_id
     )
-    .fetch_optional(pool)
+    .fetch_optional(&mut **tx)
     .await?;
 
-    if existing_quote.is_none() {
-        return Ok(None); // Quote doesn't exist
+    let Some(existing_quote) = existing_quote else {
+        return Ok(None); // Quote doesn't exist or is in the trash
+    };
+    if expected_version.is_some_and(|version| version != existing_quote.version) {
+        return Ok(None); // Quote was changed since the client read it
     }
 
-    let created_at = existing_quote.unwrap().created_at;
+    let author_id = match request.author_id {
+        Some(author_id) => author_id,
+        None => find_or_create_author(tx, &request.source, now).await?,
+    };
 
-    // Update the quote
-    sqlx::query!(
-        "UPDATE quotes SET quote = ?, source = ?, updated_at = ? WHERE id = ?",
+    // Update the quote, unless another change got in first
+    let citation = &request.citation;
+    let updated = sqlx::query!(
+        "UPDATE quotes SET quote = ?, source = ?, author_id = ?, work = ?, year = ?, page = ?, url = ?, note = ?, updated_at = ?, updated_by = ?, version = version + 1 WHERE id = ? AND version = ?",
         request.quote,
         request.source,
+        author_id,
+        citation.work,
+        citation.year,
+        citation.page,
+        citation.url,
+        citation.note,
         now,
-        quote_id
+        user_id,
+        quote_id,
+        existing_quote.version
     )
-    .execute(pool)
+    .execute(&mut **tx)
     .await?;
-
-    // Delete existing tags for this quote
-    sqlx::query!("DELETE FROM tags WHERE quote_id = ?", quote_id)
-        .execute(pool)
-        .await?;
-
-    // Insert new tags if provided
-    let mut tag_names = Vec::new();
-    if let Some(tags) = request.tags {
-        // Use HashSet to remove duplicates and filter empty strings
-        let unique_tags: HashSet<String> = tags
-            .into_iter()
-            .map(|s| s.trim().to_string())
-            .filter(|s| !s.is_empty())
-            .collect();
-
-        for tag in &unique_tags {
-            sqlx::query!(
-                "INSERT INTO tags (quote_id, name, created_at, updated_at) VALUES (?, ?, ?, ?)",
-                quote_id,
-                tag,
-                now,
-                now
-            )
-            .execute(pool)
-            .await?;
-        }
-
-        tag_names = unique_tags.into_iter().collect();
-        tag_names.sort(); // Sort for consistent ordering
+    if updated.rows_affected() == 0 {
+        return Ok(None);
     }
 
+    // Replace the quote's tags
+    let tag_names = set_quote_tags(tx, quote_id, request.tags.unwrap_or_default(), now).await?;
+    record_revision(tx, quote_id, action, restored_from, Some(user_id), now).await?;
+
     // Return the updated quote with tags
     Ok(Some(QuoteWithTags {
         id: quote_id,
         quote: request.quote,
         source: request.source,
-        created_at,
+        author_id: Some(author_id),
+        citation: request.citation,
+        created_at: existing_quote.created_at,
         updated_at: now,
+        created_by: existing_quote.created_by,
+        updated_by: Some(user_id),
+        version: existing_quote.version + 1,
         tags: tag_names,
+        snippet: None,
+        deleted_at: None,
     }))
 }
 
-// Function to delete a quote by ID
-pub async fn delete_quote(pool: &Pool<Sqlite>, quote_id: i64) -> Result<bool, sqlx::Error> {
-    // First check if the quote exists
-    let exists = sqlx::query!("SELECT id FROM quotes WHERE id = ?", quote_id)
-        .fetch_optional(pool)
-        .await?;
-
-    if exists.is_none() {
-        return Ok(false); // Quote doesn't exist
-    }
+/// Names of a quote's tags as a JSON array in alphabetical order, for queries
+/// selecting from `quotes`
+const QUOTE_TAGS_JSON: &str = "(SELECT json_group_array(name) FROM (SELECT tags.name FROM quote_tags INNER JOIN tags ON tags.id = quote_tags.tag_id WHERE quote_tags.quote_id = quotes.id ORDER BY tags.name))";
 
-    // Delete the quote (tags will be deleted automatically due to CASCADE)
-    let result = sqlx::query!("DELETE FROM quotes WHERE id = ?", quote_id)
-        .execute(pool)
-        .await?;
+/// Start a statement recording the current state of quotes as their next
+/// revisions. The caller appends the `WHERE` clause picking the quotes.
+fn insert_revisions<'a>(
+    action: RevisionAction,
+    restored_from: Option<i64>,
+    user_id: Option<i64>,
+    now: DateTime<Utc>,
+) -> QueryBuilder<'a, Sqlite> {
+    let mut builder = QueryBuilder::new(
+        "INSERT INTO quote_revisions (quote_id, revision, action, restored_from, quote, source, author_id, work, year, page, url, note, tags, user_id, created_at) \
+         SELECT quotes.id, COALESCE((SELECT MAX(revision) FROM quote_revisions WHERE quote_revisions.quote_id = quotes.id), 0) + 1, ",
+    );
+    builder.push_bind(action);
+    builder.push(", ");
+    builder.push_bind(restored_from);
+    builder.push(
+        ", quotes.quote, quotes.source, quotes.author_id, quotes.work, quotes.year, quotes.page, quotes.url, quotes.note, ",
+    );
+    builder.push(QUOTE_TAGS_JSON);
+    builder.push(", ");
+    builder.push_bind(user_id);
+    builder.push(", ");
+    builder.push_bind(now);
+    builder.push(" FROM quotes");
+    builder
+}
 
-    // Return true if a row was affected (deleted)
-    Ok(result.rows_affected() > 0)
+// Function to record the current state of a quote as its next revision
+async fn record_revision(
+    tx: &mut Transaction<'_, Sqlite>,
+    quote_id: i64,
+    action: RevisionAction,
+    restored_from: Option<i64>,
+    user_id: Option<i64>,
+    now: DateTime<Utc>,
+) -> Result<(), sqlx::Error> {
+    let mut insert = insert_revisions(action, restored_from, user_id, now);
+    insert.push(" WHERE quotes.id = ");
+    insert.push_bind(quote_id);
+    insert.build().execute(&mut **tx).await?;
+    Ok(())
 }
 
-// Function to get a quote by ID
-pub async fn get_quote_by_id(
-    pool: &Pool<Sqlite>,
+// Function to replace the tags of a quote, creating tags that do not exist yet.
+// Returns the quote's tag names in alphabetical order.
+async fn set_quote_tags(
+    tx: &mut Transaction<'_, Sqlite>,
     quote_id: i64,
-) -> Result<Option<QuoteWithTags>, sqlx::Error> {
-    // Query the specific quote
-    let quote = sqlx::query_as!(
-        Quote,
-        "SELECT id, quote, source, created_at as \"created_at: DateTime<Utc>\", updated_at as \"updated_at: DateTime<Utc>\" FROM quotes WHERE id = ?",
-        quote_id
-    )
-        .fetch_optional(pool)
+    tags: impl IntoIterator<Item = String>,
+    now: DateTime<Utc>,
+) -> Result<Vec<String>, sqlx::Error> {
+    // Remove duplicates and empty names
+    let unique_tags: BTreeSet<String> = tags
+        .into_iter()
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect();
+
+    sqlx::query!("DELETE FROM quote_tags WHERE quote_id = ?", quote_id)
+        .execute(&mut **tx)
         .await?;
 
-    match quote {
-        Some(quote) => {
-            // Get tags for this quote
-            let tags = sqlx::query_as!(
-                Tag,
-                "SELECT id, quote_id, name, created_at as \"created_at: DateTime<Utc>\", updated_at as \"updated_at: DateTime<Utc>\" FROM tags WHERE quote_id = ?",
-                quote.id
-            )
-                .fetch_all(pool)
-                .await?;
-
-            // Extract tag names
-            let tag_names = tags.into_iter().map(|t| t.name).collect();
-
-            Ok(Some(QuoteWithTags {
-                id: quote.id,
-                quote: quote.quote,
-                source: quote.source,
-                created_at: quote.created_at,
-                updated_at: quote.updated_at,
-                tags: tag_names,
-            }))
-        }
-        None => Ok(None),
-    }
-}
+    for tag in &unique_tags {
+        sqlx::query!(
+            "INSERT INTO tags (name, created_at, updated_at) VALUES (?, ?, ?) ON CONFLICT (name) DO NOTHING",
+            tag,
+            now,
+            now
+        )
+        .execute(&mut **tx)
+        .await?;
 
-// Function to search quotes with optional filters
-pub async fn search_quotes(
+        sqlx::query!(
+            "INSERT INTO quote_tags (quote_id, tag_id, created_at) SELECT ?, id, ? FROM tags WHERE name = ?",
+            quote_id,
+            now,
+            tag
+        )
+        .execute(&mut **tx)
+        .await?;
+    }
+
+    delete_unused_tags(tx).await?;
+    Ok(unique_tags.into_iter().collect())
+}
+
+// Function to delete tags no longer attached to any quote
+async fn delete_unused_tags(tx: &mut Transaction<'_, Sqlite>) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "DELETE FROM tags WHERE NOT EXISTS (SELECT 1 FROM quote_tags WHERE quote_tags.tag_id = tags.id)"
+    )
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+// Function to move a quote to the trash, recording it as a new revision. Its
+// tags and revisions are kept until it is purged. With an `expected_version`,
+// the quote is only deleted if it is still at that version.
+pub async fn delete_quote(
+    pool: &Pool<Sqlite>,
+    quote_id: i64,
+    user_id: i64,
+    expected_version: Option<i64>,
+) -> Result<bool, sqlx::Error> {
+    let now = Utc::now();
+
+    let mut tx = pool.begin().await?;
+    let result = sqlx::query!(
+        "UPDATE quotes SET deleted_at = ?, deleted_by = ?, version = version + 1 WHERE id = ? AND deleted_at IS NULL AND version = COALESCE(?, version)",
+        now,
+        user_id,
+        quote_id,
+        expected_version
+    )
+    .execute(&mut *tx)
+    .await?;
+    if result.rows_affected() == 0 {
+        return Ok(false);
+    }
+    record_revision(
+        &mut tx,
+        quote_id,
+        RevisionAction::Delete,
+        None,
+        Some(user_id),
+        now,
+    )
+    .await?;
+    tx.commit().await?;
+
+    // Return true if a quote was moved to the trash
+    Ok(true)
+}
+
+// Function to list the quotes in the trash, optionally only those created by
+// one user, most recently deleted first
+pub async fn list_trash(
     pool: &Pool<Sqlite>,
-    search_params: crate::api::SearchParams,
+    created_by: Option<i64>,
 ) -> Result<Vec<QuoteWithTags>, sqlx::Error> {
-    // Convert empty strings to None for proper matching
-    let quote_param = search_params.quote.filter(|s| !s.trim().is_empty());
-    let source_param = search_params.source.filter(|s| !s.trim().is_empty());
-    let tag_param = search_params.tag.filter(|s| !s.trim().is_empty());
-
-    // Handle different search scenarios with specific queries
-    let quotes = match (&quote_param, &source_param, &tag_param) {
-        // No search parameters - return all quotes
-        (None, None, None) => {
-            sqlx::query_as!(
-                Quote,
-                "SELECT id, quote, source, created_at as \"created_at: DateTime<Utc>\", updated_at as \"updated_at: DateTime<Utc>\" FROM quotes ORDER BY created_at DESC"
-            )
-            .fetch_all(pool)
-            .await?
-        }
-        // Only quote search
-        (Some(quote_text), None, None) => {
-            let search_pattern = format!("%{}%", quote_text.trim());
-            sqlx::query_as!(
-                Quote,
-                "SELECT id, quote, source, created_at as \"created_at: DateTime<Utc>\", updated_at as \"updated_at: DateTime<Utc>\" FROM quotes WHERE quote LIKE ? ORDER BY created_at DESC",
-                search_pattern
-            )
-            .fetch_all(pool)
-            .await?
-        }
-        // Only source search
-        (None, Some(source_text), None) => {
-            let search_pattern = format!("%{}%", source_text.trim());
-            sqlx::query_as!(
-                Quote,
-                "SELECT id, quote, source, created_at as \"created_at: DateTime<Utc>\", updated_at as \"updated_at: DateTime<Utc>\" FROM quotes WHERE source LIKE ? ORDER BY created_at DESC",
-                search_pattern
-            )
-            .fetch_all(pool)
-            .await?
-        }
-        // Only tag search
-        (None, None, Some(tag_text)) => {
-            let search_pattern = format!("%{}%", tag_text.trim());
-            sqlx::query_as!(
-                Quote,
-                "SELECT DISTINCT quotes.id, quotes.quote, quotes.source, quotes.created_at as \"created_at: DateTime<Utc>\", quotes.updated_at as \"updated_at: DateTime<Utc>\" FROM quotes INNER JOIN tags ON quotes.id = tags.quote_id WHERE tags.name LIKE ? ORDER BY quotes.created_at DESC",
-                search_pattern
-            )
-            .fetch_all(pool)
-            .await?
-        }
-        // Quote and source search
-        (Some(quote_text), Some(source_text), None) => {
-            let quote_pattern = format!("%{}%", quote_text.trim());
-            let source_pattern = format!("%{}%", source_text.trim());
-            sqlx::query_as!(
-                Quote,
-                "SELECT id, quote, source, created_at as \"created_at: DateTime<Utc>\", updated_at as \"updated_at: DateTime<Utc>\" FROM quotes WHERE quote LIKE ? AND source LIKE ? ORDER BY created_at DESC",
-                quote_pattern, source_pattern
-            )
-            .fetch_all(pool)
-            .await?
-        }
-        // Quote and tag search
-        (Some(quote_text), None, Some(tag_text)) => {
-            let quote_pattern = format!("%{}%", quote_text.trim());
-            let tag_pattern = format!("%{}%", tag_text.trim());
-            sqlx::query_as!(
-                Quote,
-                "SELECT DISTINCT quotes.id, quotes.quote, quotes.source, quotes.created_at as \"created_at: DateTime<Utc>\", quotes.updated_at as \"updated_at: DateTime<Utc>\" FROM quotes INNER JOIN tags ON quotes.id = tags.quote_id WHERE quotes.quote LIKE ? AND tags.name LIKE ? ORDER BY quotes.created_at DESC",
-                quote_pattern, tag_pattern
-            )
-            .fetch_all(pool)
-            .await?
+    let mut select = select_quotes(false);
+    select.push(" WHERE quotes.deleted_at IS NOT NULL");
+    if let Some(user_id) = created_by {
+        select.push(" AND quotes.created_by = ");
+        select.push_bind(user_id);
+    }
+    select.push(" ORDER BY quotes.deleted_at DESC, quotes.id DESC");
+    let rows: Vec<QuoteRow> = select.build_query_as().fetch_all(pool).await?;
+
+    Ok(rows.into_iter().map(QuoteWithTags::from).collect())
+}
+
+// Function to get a quote in the trash by ID
+pub async fn get_deleted_quote(
+    pool: &Pool<Sqlite>,
+    quote_id: i64,
+) -> Result<Option<QuoteWithTags>, sqlx::Error> {
+    let mut select = select_quotes(false);
+    select.push(" WHERE quotes.deleted_at IS NOT NULL AND quotes.id = ");
+    select.push_bind(quote_id);
+    let row: Option<QuoteRow> = select.build_query_as().fetch_optional(pool).await?;
+
+    Ok(row.map(QuoteWithTags::from))
+}
+
+// Function to take a quote back out of the trash, recording it as a new revision
+pub async fn restore_quote(
+    pool: &Pool<Sqlite>,
+    quote_id: i64,
+    user_id: i64,
+) -> Result<bool, sqlx::Error> {
+    let now = Utc::now();
+
+    let mut tx = pool.begin().await?;
+    let result = sqlx::query!(
+        "UPDATE quotes SET deleted_at = NULL, deleted_by = NULL, version = version + 1 WHERE id = ? AND deleted_at IS NOT NULL",
+        quote_id
+    )
+    .execute(&mut *tx)
+    .await?;
+    if result.rows_affected() == 0 {
+        return Ok(false);
+    }
+    record_revision(
+        &mut tx,
+        quote_id,
+        RevisionAction::Undelete,
+        None,
+        Some(user_id),
+        now,
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(true)
+}
+
+// Function to permanently delete a quote in the trash
+pub async fn purge_quote(pool: &Pool<Sqlite>, quote_id: i64) -> Result<bool, sqlx::Error> {
+    // Its tag links and revisions will be deleted automatically due to CASCADE
+    let mut tx = pool.begin().await?;
+    let result = sqlx::query!(
+        "DELETE FROM quotes WHERE id = ? AND deleted_at IS NOT NULL",
+        quote_id
+    )
+    .execute(&mut *tx)
+    .await?;
+    delete_unused_tags(&mut tx).await?;
+    tx.commit().await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+// Function to permanently delete the quotes moved to the trash before a given
+// time. Returns the number of quotes deleted.
+pub async fn purge_trash(
+    pool: &Pool<Sqlite>,
+    deleted_before: DateTime<Utc>,
+) -> Result<u64, sqlx::Error> {
+    // Compare as Julian days, as stored timestamps are not all formatted alike
+    let mut tx = pool.begin().await?;
+    let result = sqlx::query!(
+        "DELETE FROM quotes WHERE deleted_at IS NOT NULL AND julianday(deleted_at) < julianday(?)",
+        deleted_before
+    )
+    .execute(&mut *tx)
+    .await?;
+    delete_unused_tags(&mut tx).await?;
+    tx.commit().await?;
+
+    Ok(result.rows_affected())
+}
+
+// Function to get a quote by ID
+pub async fn get_quote_by_id(
+    pool: &Pool<Sqlite>,
+    quote_id: i64,
+) -> Result<Option<QuoteWithTags>, sqlx::Error> {
+    let mut select = select_quotes(false);
+    select.push(" WHERE quotes.deleted_at IS NULL AND quotes.id = ");
+    select.push_bind(quote_id);
+    let row: Option<QuoteRow> = select.build_query_as().fetch_optional(pool).await?;
+
+    Ok(row.map(QuoteWithTags::from))
+}
+
+// Function to search quotes with optional filters, returning one page of the matches
+pub async fn search_quotes(
+    pool: &Pool<Sqlite>,
+    search_params: SearchParams,
+) -> Result<QuotePage, sqlx::Error> {
+    let Some(query) = search_params
+        .q
+        .as_deref()
+        .map(str::trim)
+        .filter(|q| !q.is_empty())
+    else {
+        return query_quotes(pool, &search_params, None).await;
+    };
+
+    // Treat text that is not valid query syntax as a list of plain words
+    let Some(full_text) = fts_query(query).or_else(|| plain_words(query)) else {
+        return Ok(QuotePage {
+            quotes: Vec::new(),
+            total: 0,
+        });
+    };
+
+    match query_quotes(pool, &search_params, Some(&full_text)).await {
+        // Only reached if the checks above let through a query SQLite rejects
+        Err(err) if is_search_syntax_error(&err) => match plain_words(query) {
+            Some(query) => query_quotes(pool, &search_params, Some(&query)).await,
+            None => Ok(QuotePage {
+                quotes: Vec::new(),
+                total: 0,
+            }),
+        },
+        result => result,
+    }
+}
+
+// Function to count the quotes matching a search and load one page of them.
+// `full_text` is the FTS5 query to match, if any.
+async fn query_quotes(
+    pool: &Pool<Sqlite>,
+    search_params: &SearchParams,
+    full_text: Option<&str>,
+) -> Result<QuotePage, sqlx::Error> {
+    let mut count = QueryBuilder::new("SELECT COUNT(*) FROM quotes");
+    push_quote_filters(&mut count, search_params, full_text);
+    let total: i64 = count.build_query_scalar().fetch_one(pool).await?;
+
+    let mut select = select_quotes(full_text.is_some());
+    push_quote_filters(&mut select, search_params, full_text);
+    select.push(" ORDER BY ");
+    select.push(quote_order(search_params.sort(), search_params.order()));
+    select.push(" LIMIT ");
+    select.push_bind(search_params.limit());
+    select.push(" OFFSET ");
+    select.push_bind(search_params.offset());
+    let rows: Vec<QuoteRow> = select.build_query_as().fetch_all(pool).await?;
+    let quotes = rows.into_iter().map(QuoteWithTags::from).collect();
+
+    Ok(QuotePage { quotes, total })
+}
+
+/// Start a query loading quotes as [`QuoteRow`]s, each with its tags gathered
+/// into a JSON array so no further queries are needed. With `snippet`, the
+/// query must join `quotes_fts` and match against it.
+fn select_quotes<'a>(snippet: bool) -> QueryBuilder<'a, Sqlite> {
+    let mut builder = QueryBuilder::new(
+        "SELECT quotes.id, quotes.quote, quotes.source, quotes.author_id, quotes.work, quotes.year, quotes.page, quotes.url, quotes.note, quotes.created_at, quotes.updated_at, quotes.created_by, quotes.updated_by, quotes.version, quotes.deleted_at, ",
+    );
+    builder.push(QUOTE_TAGS_JSON);
+    builder.push(" AS tags, ");
+    builder.push(if snippet {
+        "snippet(quotes_fts, -1, char(2), char(3), '…', 16)"
+    } else {
+        "NULL"
+    });
+    builder.push(" AS snippet FROM quotes");
+    builder
+}
+
+/// Append the joins and `WHERE` clause selecting the quotes that match a search,
+/// leaving out quotes in the trash. Every filter is optional and they all
+/// combine with `AND`.
+fn push_quote_filters(
+    builder: &mut QueryBuilder<'_, Sqlite>,
+    search_params: &SearchParams,
+    full_text: Option<&str>,
+) {
+    let text = |param: &Option<String>| {
+        param
+            .as_deref()
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(|s| format!("%{s}%"))
+    };
+
+    if full_text.is_some() {
+        builder.push(" INNER JOIN quotes_fts ON quotes_fts.rowid = quotes.id");
+    }
+    builder.push(" WHERE quotes.deleted_at IS NULL");
+
+    if let Some(query) = full_text {
+        builder.push(" AND quotes_fts MATCH ");
+        builder.push_bind(query.to_string());
+    }
+    if let Some(pattern) = text(&search_params.quote) {
+        builder.push(" AND quotes.quote LIKE ");
+        builder.push_bind(pattern);
+    }
+    if let Some(pattern) = text(&search_params.source) {
+        builder.push(" AND quotes.source LIKE ");
+        builder.push_bind(pattern);
+    }
+    if let Some(pattern) = text(&search_params.tag) {
+        builder.push(
+            " AND EXISTS (SELECT 1 FROM quote_tags INNER JOIN tags ON tags.id = quote_tags.tag_id WHERE quote_tags.quote_id = quotes.id AND tags.name LIKE ",
+        );
+        builder.push_bind(pattern);
+        builder.push(")");
+    }
+    for tag in SearchParams::tag_list(&search_params.tags) {
+        builder.push(
+            " AND EXISTS (SELECT 1 FROM quote_tags INNER JOIN tags ON tags.id = quote_tags.tag_id WHERE quote_tags.quote_id = quotes.id AND tags.name = ",
+        );
+        builder.push_bind(tag);
+        builder.push(")");
+    }
+    for tag in SearchParams::tag_list(&search_params.exclude_tags) {
+        builder.push(
+            " AND NOT EXISTS (SELECT 1 FROM quote_tags INNER JOIN tags ON tags.id = quote_tags.tag_id WHERE quote_tags.quote_id = quotes.id AND tags.name = ",
+        );
+        builder.push_bind(tag);
+        builder.push(")");
+    }
+    if let Some(author_id) = search_params.author_id {
+        builder.push(" AND quotes.author_id = ");
+        builder.push_bind(author_id);
+    }
+    if let Some(user_id) = search_params.created_by {
+        builder.push(" AND quotes.created_by = ");
+        builder.push_bind(user_id);
+    }
+    // Compare as Julian days, as stored timestamps are not all formatted alike
+    if let Some(after) = search_params.created_after {
+        builder.push(" AND julianday(quotes.created_at) >= julianday(");
+        builder.push_bind(after);
+        builder.push(")");
+    }
+    if let Some(before) = search_params.created_before {
+        builder.push(" AND julianday(quotes.created_at) < julianday(");
+        builder.push_bind(before);
+        builder.push(")");
+    }
+}
+
+/// `ORDER BY` terms for a sort, with the quote ID breaking ties
+fn quote_order(sort: QuoteSort, order: SortOrder) -> &'static str {
+    match (sort, order) {
+        (QuoteSort::Created, SortOrder::Asc) => "quotes.created_at ASC, quotes.id ASC",
+        (QuoteSort::Created, SortOrder::Desc) => "quotes.created_at DESC, quotes.id DESC",
+        (QuoteSort::Updated, SortOrder::Asc) => "quotes.updated_at ASC, quotes.id ASC",
+        (QuoteSort::Updated, SortOrder::Desc) => "quotes.updated_at DESC, quotes.id DESC",
+        (QuoteSort::Source, SortOrder::Asc) => "quotes.source COLLATE NOCASE ASC, quotes.id ASC",
+        (QuoteSort::Source, SortOrder::Desc) => "quotes.source COLLATE NOCASE DESC, quotes.id DESC",
+        (QuoteSort::Id, SortOrder::Asc) => "quotes.id ASC",
+        (QuoteSort::Id, SortOrder::Desc) => "quotes.id DESC",
+        // Lower bm25 scores are better matches
+        (QuoteSort::Relevance, SortOrder::Asc) => "bm25(quotes_fts) DESC, quotes.id ASC",
+        (QuoteSort::Relevance, SortOrder::Desc) => "bm25(quotes_fts) ASC, quotes.id ASC",
+    }
+}
+
+/// Columns of `quotes_fts` a search term can be limited to with `column:`
+const SEARCH_COLUMNS: [&str; 3] = ["quote", "source", "tags"];
+
+/// Rewrite a search in the supported syntax, with every word and phrase quoted
+/// so FTS5 cannot reject its characters: words, `"phrases"`, a trailing `*` for
+/// prefixes, `column:` filters, and `AND`, `OR` and `NOT` between terms.
+/// Returns `None` when the text does not follow this syntax.
+fn fts_query(query: &str) -> Option<String> {
+    let is_column = |column: &str| SEARCH_COLUMNS.contains(&column.to_lowercase().as_str());
+
+    let mut parts = Vec::new();
+    let mut expect_term = true;
+    let mut chars = query.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        if chars.peek().is_none() {
+            break;
         }
-        // Source and tag search
-        (None, Some(source_text), Some(tag_text)) => {
-            let source_pattern = format!("%{}%", source_text.trim());
-            let tag_pattern = format!("%{}%", tag_text.trim());
-            sqlx::query_as!(
-                Quote,
-                "SELECT DISTINCT quotes.id, quotes.quote, quotes.source, quotes.created_at as \"created_at: DateTime<Utc>\", quotes.updated_at as \"updated_at: DateTime<Utc>\" FROM quotes INNER JOIN tags ON quotes.id = tags.quote_id WHERE quotes.source LIKE ? AND tags.name LIKE ? ORDER BY quotes.created_at DESC",
-                source_pattern, tag_pattern
-            )
-            .fetch_all(pool)
-            .await?
+
+        let mut word = String::new();
+        while let Some(c) = chars.next_if(|c| !c.is_whitespace() && *c != '"') {
+            word.push(c);
         }
-        // All three search criteria
-        (Some(quote_text), Some(source_text), Some(tag_text)) => {
-            let quote_pattern = format!("%{}%", quote_text.trim());
-            let source_pattern = format!("%{}%", source_text.trim());
-            let tag_pattern = format!("%{}%", tag_text.trim());
-            sqlx::query_as!(
-                Quote,
-                "SELECT DISTINCT quotes.id, quotes.quote, quotes.source, quotes.created_at as \"created_at: DateTime<Utc>\", quotes.updated_at as \"updated_at: DateTime<Utc>\" FROM quotes INNER JOIN tags ON quotes.id = tags.quote_id WHERE quotes.quote LIKE ? AND quotes.source LIKE ? AND tags.name LIKE ? ORDER BY quotes.created_at DESC",
-                quote_pattern, source_pattern, tag_pattern
-            )
-            .fetch_all(pool)
-            .await?
+
+        let (column, text, prefix) = if chars.next_if_eq(&'"').is_some() {
+            // A phrase, possibly after a column filter
+            let column = match word.strip_suffix(':') {
+                Some(column) if is_column(column) => Some(column.to_lowercase()),
+                None if word.is_empty() => None,
+                _ => return None,
+            };
+            let mut phrase = String::new();
+            loop {
+                match chars.next()? {
+                    '"' => break,
+                    c => phrase.push(c),
+                }
+            }
+            let prefix = chars.next_if_eq(&'*').is_some();
+            if chars.peek().is_some_and(|c| !c.is_whitespace()) {
+                return None;
+            }
+            (column, phrase, prefix)
+        } else if matches!(word.as_str(), "AND" | "OR" | "NOT") {
+            if expect_term {
+                return None;
+            }
+            parts.push(word);
+            expect_term = true;
+            continue;
+        } else {
+            let (column, text) = match word.split_once(':') {
+                Some((column, text)) if is_column(column) => {
+                    (Some(column.to_lowercase()), text.to_string())
+                }
+                _ => (None, word),
+            };
+            match text.strip_suffix('*') {
+                Some(text) => (column, text.to_string(), true),
+                None => (column, text, false),
+            }
+        };
+
+        if text.trim().is_empty() {
+            return None;
         }
-    };
+        let column = column
+            .map(|column| format!("{column}:"))
+            .unwrap_or_default();
+        let prefix = if prefix { "*" } else { "" };
+        parts.push(format!("{column}\"{text}\"{prefix}"));
+        expect_term = false;
+    }
 
-    let mut quotes_with_tags = Vec::new();
+    (!expect_term).then(|| parts.join(" "))
+}
 
-    // For each quote, get its tags
-    for quote in quotes {
-        let tags = sqlx::query_as!(
-            Tag,
-            "SELECT id, quote_id, name, created_at as \"created_at: DateTime<Utc>\", updated_at as \"updated_at: DateTime<Utc>\" FROM tags WHERE quote_id = ?",
-            quote.id
-        )
-            .fetch_all(pool)
-            .await?;
+/// Whether a full-text search failed because of its query rather than the database
+fn is_search_syntax_error(err: &sqlx::Error) -> bool {
+    let sqlx::Error::Database(err) = err else {
+        return false;
+    };
+    let message = err.message();
+    message.starts_with("fts5:")
+        || message.starts_with("no such column")
+        || message.starts_with("unterminated string")
+        || message.starts_with("unknown special query")
+}
 
-        // Extract tag names
-        let tag_names = tags.into_iter().map(|t| t.name).collect();
+/// Rewrite a search as plain words that must all match, keeping a trailing `*`
+/// for prefix searches. Returns `None` when no words are left.
+fn plain_words(query: &str) -> Option<String> {
+    let words: Vec<String> = query
+        .split_whitespace()
+        .filter_map(|word| {
+            let (word, prefix) = match word.strip_suffix('*') {
+                Some(word) => (word, "*"),
+                None => (word, ""),
+            };
+            let word = word.replace('"', "");
+            (!word.is_empty()).then(|| format!("\"{word}\"{prefix}"))
+        })
+        .collect();
+    (!words.is_empty()).then(|| words.join(" "))
+}
 
-        quotes_with_tags.push(QuoteWithTags {
-            id: quote.id,
-            quote: quote.quote,
-            source: quote.source,
-            created_at: quote.created_at,
-            updated_at: quote.updated_at,
-            tags: tag_names,
-        });
+/// HTML-escape a snippet and wrap its matches in `<mark>`
+fn highlight(snippet: &str) -> String {
+    let mut html = String::with_capacity(snippet.len());
+    for c in snippet.chars() {
+        match c {
+            MATCH_START => html.push_str("<mark>"),
+            MATCH_END => html.push_str("</mark>"),
+            '&' => html.push_str("&amp;"),
+            '<' => html.push_str("&lt;"),
+            '>' => html.push_str("&gt;"),
+            '"' => html.push_str("&quot;"),
+            '\'' => html.push_str("&#39;"),
+            c => html.push(c),
+        }
     }
+    html
+}
 
-    Ok(quotes_with_tags)
+// Function to get the quotes created by a user, newest first
+pub async fn get_quotes_by_creator(
+    pool: &Pool<Sqlite>,
+    user_id: i64,
+) -> Result<Vec<QuoteWithTags>, sqlx::Error> {
+    let mut select = select_quotes(false);
+    select.push(" WHERE quotes.deleted_at IS NULL AND quotes.created_by = ");
+    select.push_bind(user_id);
+    select.push(" ORDER BY quotes.created_at DESC, quotes.id DESC");
+    let rows: Vec<QuoteRow> = select.build_query_as().fetch_all(pool).await?;
+
+    Ok(rows.into_iter().map(QuoteWithTags::from).collect())
 }
 
 // Function to get a random quote with its tags
 pub async fn get_random_quote(pool: &Pool<Sqlite>) -> Result<Option<QuoteWithTags>, sqlx::Error> {
-    // Count total quotes
-    let count = sqlx::query!("SELECT COUNT(*) as count FROM quotes")
-        .fetch_one(pool)
+    let mut select = select_quotes(false);
+    select.push(" WHERE quotes.deleted_at IS NULL ORDER BY RANDOM() LIMIT 1");
+    let row: Option<QuoteRow> = select.build_query_as().fetch_optional(pool).await?;
+
+    Ok(row.map(QuoteWithTags::from))
+}
+
+/// Start a query loading quote revisions as [`RevisionRow`]s, leaving out those of quotes in the
+/// trash. Further conditions are added with `AND`.
+fn select_revisions<'a>() -> QueryBuilder<'a, Sqlite> {
+    QueryBuilder::new(
+        "SELECT quote_id, revision, action, restored_from, quote, source, author_id, work, year, page, url, note, tags, user_id, created_at FROM quote_revisions WHERE quote_id IN (SELECT id FROM quotes WHERE deleted_at IS NULL)",
+    )
+}
+
+// Function to list the revisions of a quote, oldest first
+pub async fn list_revisions(
+    pool: &Pool<Sqlite>,
+    quote_id: i64,
+) -> Result<Vec<QuoteRevision>, sqlx::Error> {
+    let mut select = select_revisions();
+    select.push(" AND quote_id = ");
+    select.push_bind(quote_id);
+    select.push(" ORDER BY revision");
+    let rows: Vec<RevisionRow> = select.build_query_as().fetch_all(pool).await?;
+
+    Ok(rows.into_iter().map(QuoteRevision::from).collect())
+}
+
+// Function to get one revision of a quote
+pub async fn get_revision(
+    pool: &Pool<Sqlite>,
+    quote_id: i64,
+    revision: i64,
+) -> Result<Option<QuoteRevision>, sqlx::Error> {
+    let mut select = select_revisions();
+    select.push(" AND quote_id = ");
+    select.push_bind(quote_id);
+    select.push(" AND revision = ");
+    select.push_bind(revision);
+    let row: Option<RevisionRow> = select.build_query_as().fetch_optional(pool).await?;
+
+    Ok(row.map(QuoteRevision::from))
+}
+
+// Function to put a quote back the way a revision left it, recording the
+// result as a new revision. Returns `None` if the quote or revision does not
+// exist, or the quote is no longer at `expected_version`.
+pub async fn restore_revision(
+    pool: &Pool<Sqlite>,
+    quote_id: i64,
+    revision: i64,
+    user_id: i64,
+    expected_version: Option<i64>,
+) -> Result<Option<QuoteWithTags>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let mut select = select_revisions();
+    select.push(" AND quote_id = ");
+    select.push_bind(quote_id);
+    select.push(" AND revision = ");
+    select.push_bind(revision);
+    let row: Option<RevisionRow> = select.build_query_as().fetch_optional(&mut *tx).await?;
+    let Some(old) = row.map(QuoteRevision::from) else {
+        return Ok(None);
+    };
+
+    // An author deleted since is found or created again from the source
+    let author_id = match old.author_id {
+        Some(author_id) => sqlx::query_scalar!(
+            "SELECT EXISTS(SELECT 1 FROM authors WHERE id = ?) as \"exists!: bool\"",
+            author_id
+        )
+        .fetch_one(&mut *tx)
         .await?
-        .count;
+        .then_some(author_id),
+        None => None,
+    };
+    let request = UpdateQuoteRequest {
+        quote: old.quote,
+        source: old.source,
+        author_id,
+        citation: old.citation,
+        tags: Some(old.tags),
+    };
+    let quote = update_quote_in(
+        &mut tx,
+        quote_id,
+        request,
+        user_id,
+        expected_version,
+        RevisionAction::Restore,
+        Some(revision),
+    )
+    .await?;
+    if quote.is_some() {
+        tx.commit().await?;
+    }
+    Ok(quote)
+}
+
+// Function to list all tags with the number of quotes carrying each
+pub async fn list_tags(pool: &Pool<Sqlite>) -> Result<Vec<TagWithCount>, sqlx::Error> {
+    sqlx::query_as!(
+        TagWithCount,
+        "SELECT tags.id as \"id!\", tags.name, COUNT(quotes.id) as \"quote_count!: i64\" FROM tags LEFT JOIN quote_tags ON quote_tags.tag_id = tags.id LEFT JOIN quotes ON quotes.id = quote_tags.quote_id AND quotes.deleted_at IS NULL GROUP BY tags.id ORDER BY tags.name"
+    )
+    .fetch_all(pool)
+    .await
+}
+
+// Function to get a tag by ID with the number of quotes carrying it
+pub async fn get_tag(
+    pool: &Pool<Sqlite>,
+    tag_id: i64,
+) -> Result<Option<TagWithCount>, sqlx::Error> {
+    sqlx::query_as!(
+        TagWithCount,
+        "SELECT tags.id as \"id!\", tags.name, COUNT(quotes.id) as \"quote_count!: i64\" FROM tags LEFT JOIN quote_tags ON quote_tags.tag_id = tags.id LEFT JOIN quotes ON quotes.id = quote_tags.quote_id AND quotes.deleted_at IS NULL WHERE tags.id = ? GROUP BY tags.id",
+        tag_id
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+// Function to rename a tag on every quote carrying it. Fails with a unique
+// violation if another tag already has the name.
+pub async fn rename_tag(
+    pool: &Pool<Sqlite>,
+    tag_id: i64,
+    name: &str,
+    user_id: i64,
+) -> Result<Option<TagWithCount>, sqlx::Error> {
+    let now = Utc::now();
+    let mut tx = pool.begin().await?;
+
+    let renamed = sqlx::query!(
+        "UPDATE tags SET name = ?, updated_at = ? WHERE id = ?",
+        name,
+        now,
+        tag_id
+    )
+    .execute(&mut *tx)
+    .await?;
+    if renamed.rows_affected() == 0 {
+        return Ok(None);
+    }
+
+    sqlx::query!(
+        "UPDATE quotes SET updated_at = ?, updated_by = ?, version = version + 1 WHERE id IN (SELECT quote_id FROM quote_tags WHERE tag_id = ?)",
+        now,
+        user_id,
+        tag_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    let mut revisions = insert_revisions(RevisionAction::Tag, None, Some(user_id), now);
+    revisions.push(" WHERE quotes.id IN (SELECT quote_id FROM quote_tags WHERE tag_id = ");
+    revisions.push_bind(tag_id);
+    revisions.push(")");
+    revisions.build().execute(&mut *tx).await?;
 
-    if count == 0 {
+    tx.commit().await?;
+    get_tag(pool, tag_id).await
+}
+
+// Function to move every quote from one tag to another and delete the first.
+// Returns `None` if either tag does not exist.
+pub async fn merge_tags(
+    pool: &Pool<Sqlite>,
+    from_id: i64,
+    into_id: i64,
+    user_id: i64,
+) -> Result<Option<TagWithCount>, sqlx::Error> {
+    let now = Utc::now();
+    let mut tx = pool.begin().await?;
+
+    let found = sqlx::query_scalar!(
+        "SELECT COUNT(*) FROM tags WHERE id IN (?, ?)",
+        from_id,
+        into_id
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+    if found != 2 {
         return Ok(None);
     }
 
-    // Get random quote
-    let quote = sqlx::query_as!(
-        Quote,
-        "SELECT id, quote, source, created_at as \"created_at: DateTime<Utc>\", updated_at as \"updated_at: DateTime<Utc>\" FROM quotes ORDER BY RANDOM() LIMIT 1"
+    let quote_ids =
+        sqlx::query_scalar!("SELECT quote_id FROM quote_tags WHERE tag_id = ?", from_id)
+            .fetch_all(&mut *tx)
+            .await?;
+
+    sqlx::query!(
+        "UPDATE quotes SET updated_at = ?, updated_by = ?, version = version + 1 WHERE id IN (SELECT quote_id FROM quote_tags WHERE tag_id = ?)",
+        now,
+        user_id,
+        from_id
     )
-        .fetch_optional(pool)
+    .execute(&mut *tx)
+    .await?;
+
+    // Quotes carrying both tags keep a single link
+    sqlx::query!(
+        "INSERT OR IGNORE INTO quote_tags (quote_id, tag_id, created_at) SELECT quote_id, ?, created_at FROM quote_tags WHERE tag_id = ?",
+        into_id,
+        from_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    // Its remaining links are deleted automatically due to CASCADE
+    sqlx::query!("DELETE FROM tags WHERE id = ?", from_id)
+        .execute(&mut *tx)
         .await?;
 
-    match quote {
-        Some(quote) => {
-            // Get tags for this quote
-            let tags = sqlx::query_as!(
-                Tag,
-                "SELECT id, quote_id, name, created_at as \"created_at: DateTime<Utc>\", updated_at as \"updated_at: DateTime<Utc>\" FROM tags WHERE quote_id = ?",
-                quote.id
-            )
-                .fetch_all(pool)
-                .await?;
-
-            // Extract tag names
-            let tag_names = tags.into_iter().map(|t| t.name).collect();
-
-            Ok(Some(QuoteWithTags {
-                id: quote.id,
-                quote: quote.quote,
-                source: quote.source,
-                created_at: quote.created_at,
-                updated_at: quote.updated_at,
-                tags: tag_names,
-            }))
+    sqlx::query!("UPDATE tags SET updated_at = ? WHERE id = ?", now, into_id)
+        .execute(&mut *tx)
+        .await?;
+
+    if !quote_ids.is_empty() {
+        let mut revisions = insert_revisions(RevisionAction::Tag, None, Some(user_id), now);
+        revisions.push(" WHERE quotes.id IN (");
+        let mut ids = revisions.separated(", ");
+        for quote_id in quote_ids {
+            ids.push_bind(quote_id);
         }
-        None => Ok(None),
+        ids.push_unseparated(")");
+        revisions.build().execute(&mut *tx).await?;
     }
+
+    tx.commit().await?;
+    get_tag(pool, into_id).await
+}
+
+/// Start a query loading authors as [`AuthorRow`]s, with their aliases and the
+/// number of quotes linked to them
+fn select_authors<'a>() -> QueryBuilder<'a, Sqlite> {
+    QueryBuilder::new(
+        "SELECT authors.id, authors.name, \
+         (SELECT json_group_array(alias) FROM (SELECT alias FROM author_aliases WHERE author_aliases.author_id = authors.id ORDER BY alias)) AS aliases, \
+         authors.birth_year, authors.death_year, authors.bio, authors.links, \
+         (SELECT COUNT(*) FROM quotes WHERE quotes.author_id = authors.id AND quotes.deleted_at IS NULL) AS quote_count, \
+         authors.created_at, authors.updated_at FROM authors",
+    )
+}
+
+// Function to list all authors in alphabetical order
+pub async fn list_authors(pool: &Pool<Sqlite>) -> Result<Vec<Author>, sqlx::Error> {
+    let mut select = select_authors();
+    select.push(" ORDER BY authors.name");
+    let rows: Vec<AuthorRow> = select.build_query_as().fetch_all(pool).await?;
+
+    Ok(rows.into_iter().map(Author::from).collect())
+}
+
+// Function to get an author by ID
+pub async fn get_author(
+    pool: &Pool<Sqlite>,
+    author_id: i64,
+) -> Result<Option<Author>, sqlx::Error> {
+    let mut select = select_authors();
+    select.push(" WHERE authors.id = ");
+    select.push_bind(author_id);
+    let row: Option<AuthorRow> = select.build_query_as().fetch_optional(pool).await?;
+
+    Ok(row.map(Author::from))
+}
+
+// Function to check whether a name or alias already belongs to an author other
+// than `except_id`, ignoring case
+pub async fn author_name_taken(
+    pool: &Pool<Sqlite>,
+    name: &str,
+    except_id: Option<i64>,
+) -> Result<bool, sqlx::Error> {
+    let except_id = except_id.unwrap_or(0);
+    sqlx::query_scalar!(
+        "SELECT EXISTS(SELECT 1 FROM authors WHERE name = ? AND id != ?) OR EXISTS(SELECT 1 FROM author_aliases WHERE alias = ? AND author_id != ?) as \"taken!: bool\"",
+        name,
+        except_id,
+        name,
+        except_id
+    )
+    .fetch_one(pool)
+    .await
+}
+
+// Function to create a new author with its aliases
+pub async fn create_author(
+    pool: &Pool<Sqlite>,
+    request: &AuthorRequest,
+) -> Result<Author, sqlx::Error> {
+    let now = Utc::now();
+    let links = serde_json::json!(request.links).to_string();
+    let mut tx = pool.begin().await?;
+
+    let author_id = sqlx::query!(
+        "INSERT INTO authors (name, birth_year, death_year, bio, links, created_at, updated_at) VALUES (?, ?, ?, ?, ?, ?, ?)",
+        request.name,
+        request.birth_year,
+        request.death_year,
+        request.bio,
+        links,
+        now,
+        now
+    )
+    .execute(&mut *tx)
+    .await?
+    .last_insert_rowid();
+
+    set_author_aliases(&mut tx, author_id, request).await?;
+    tx.commit().await?;
+
+    get_author(pool, author_id)
+        .await?
+        .ok_or(sqlx::Error::RowNotFound)
+}
+
+// Function to replace an author's details and aliases
+pub async fn update_author(
+    pool: &Pool<Sqlite>,
+    author_id: i64,
+    request: &AuthorRequest,
+) -> Result<Option<Author>, sqlx::Error> {
+    let now = Utc::now();
+    let links = serde_json::json!(request.links).to_string();
+    let mut tx = pool.begin().await?;
+
+    let updated = sqlx::query!(
+        "UPDATE authors SET name = ?, birth_year = ?, death_year = ?, bio = ?, links = ?, updated_at = ? WHERE id = ?",
+        request.name,
+        request.birth_year,
+        request.death_year,
+        request.bio,
+        links,
+        now,
+        author_id
+    )
+    .execute(&mut *tx)
+    .await?;
+    if updated.rows_affected() == 0 {
+        return Ok(None);
+    }
+
+    set_author_aliases(&mut tx, author_id, request).await?;
+    tx.commit().await?;
+
+    get_author(pool, author_id).await
+}
+
+// Function to replace an author's aliases, leaving out duplicates and the
+// author's own name
+async fn set_author_aliases(
+    tx: &mut Transaction<'_, Sqlite>,
+    author_id: i64,
+    request: &AuthorRequest,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!("DELETE FROM author_aliases WHERE author_id = ?", author_id)
+        .execute(&mut **tx)
+        .await?;
+
+    let mut seen = HashSet::from([request.name.to_lowercase()]);
+    for alias in request.aliases.iter().map(|alias| alias.trim()) {
+        if alias.is_empty() || !seen.insert(alias.to_lowercase()) {
+            continue;
+        }
+        sqlx::query!(
+            "INSERT INTO author_aliases (author_id, alias) VALUES (?, ?)",
+            author_id,
+            alias
+        )
+        .execute(&mut **tx)
+        .await?;
+    }
+    Ok(())
+}
+
+// Function to delete an author by ID. Authors still linked to quotes, counting
+// those in the trash, are kept; returns whether the author was deleted.
+pub async fn delete_author(pool: &Pool<Sqlite>, author_id: i64) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        "DELETE FROM authors WHERE id = ? AND NOT EXISTS (SELECT 1 FROM quotes WHERE author_id = ?)",
+        author_id,
+        author_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+// Function to find the author a quote source names, creating one if there is
+// none. Sources match an author's name or alias ignoring case. Failing that,
+// a source matching exactly one author's name by surname and compatible given
+// names or initials, such as "A. Einstein" for "Albert Einstein", is added to
+// that author's aliases.
+pub async fn find_or_create_author(
+    tx: &mut Transaction<'_, Sqlite>,
+    source: &str,
+    now: DateTime<Utc>,
+) -> Result<i64, sqlx::Error> {
+    let source = source.trim();
+
+    let known = sqlx::query_scalar!(
+        "SELECT id as \"id!\" FROM authors WHERE name = ? UNION ALL SELECT author_id FROM author_aliases WHERE alias = ? LIMIT 1",
+        source,
+        source
+    )
+    .fetch_optional(&mut **tx)
+    .await?;
+    if let Some(author_id) = known {
+        return Ok(author_id);
+    }
+
+    let tokens = name_tokens(source);
+    if let Some(surname) = tokens.last() {
+        let pattern = format!("%{surname}");
+        let candidates = sqlx::query!(
+            "SELECT id as \"id!\", name FROM authors WHERE name LIKE ?",
+            pattern
+        )
+        .fetch_all(&mut **tx)
+        .await?;
+        let matches: Vec<i64> = candidates
+            .into_iter()
+            .filter(|author| same_person(&tokens, &name_tokens(&author.name)))
+            .map(|author| author.id)
+            .collect();
+
+        if let [author_id] = matches[..] {
+            sqlx::query!(
+                "INSERT INTO author_aliases (author_id, alias) VALUES (?, ?) ON CONFLICT (alias) DO NOTHING",
+                author_id,
+                source
+            )
+            .execute(&mut **tx)
+            .await?;
+            return Ok(author_id);
+        }
+    }
+
+    let author_id = sqlx::query!(
+        "INSERT INTO authors (name, created_at, updated_at) VALUES (?, ?, ?)",
+        source,
+        now,
+        now
+    )
+    .execute(&mut **tx)
+    .await?
+    .last_insert_rowid();
+    Ok(author_id)
+}
+
+// Function to link quotes without an author to the authors their sources name,
+// creating authors as needed. Returns the number of quotes linked.
+pub async fn link_unattributed_quotes(pool: &Pool<Sqlite>) -> Result<u64, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let linked = link_quotes_to_authors(&mut tx).await?;
+    tx.commit().await?;
+    Ok(linked)
+}
+
+// Function to link quotes without an author as part of a larger transaction
+async fn link_quotes_to_authors(tx: &mut Transaction<'_, Sqlite>) -> Result<u64, sqlx::Error> {
+    let now = Utc::now();
+    let mut sources =
+        sqlx::query_scalar!("SELECT DISTINCT source FROM quotes WHERE author_id IS NULL")
+            .fetch_all(&mut **tx)
+            .await?;
+
+    // Most complete names first, so shorter spellings become their aliases
+    sources.sort_by_cached_key(|source| {
+        let tokens = name_tokens(source);
+        let full_names = tokens.iter().filter(|t| t.chars().count() > 1).count();
+        (Reverse(full_names), Reverse(tokens.len()), source.clone())
+    });
+
+    let mut linked = 0;
+    for source in sources {
+        let author_id = find_or_create_author(tx, &source, now).await?;
+        // Linking fills in what the source already says, so the version and with
+        // it clients' ETags stay as they were
+        linked += sqlx::query!(
+            "UPDATE quotes SET author_id = ? WHERE author_id IS NULL AND source = ?",
+            author_id,
+            source
+        )
+        .execute(&mut **tx)
+        .await?
+        .rows_affected();
+    }
+    Ok(linked)
+}
+
+/// Lowercase words of a name, without punctuation
+fn name_tokens(name: &str) -> Vec<String> {
+    name.split(|c: char| c.is_whitespace() || c == '.' || c == ',')
+        .map(|token| {
+            token
+                .chars()
+                .filter(|c| c.is_alphanumeric() || *c == '-' || *c == '\'')
+                .collect::<String>()
+                .to_lowercase()
+        })
+        .filter(|token| !token.is_empty())
+        .collect()
+}
+
+/// Whether two names can refer to the same person: the surnames agree and the
+/// given names of the shorter name appear in order in the longer one, either
+/// spelled out or as initials
+fn same_person(a: &[String], b: &[String]) -> bool {
+    let (Some((a_surname, a_given)), Some((b_surname, b_given))) = (a.split_last(), b.split_last())
+    else {
+        return false;
+    };
+    if a_surname != b_surname {
+        return false;
+    }
+
+    let (short, long) = if a_given.len() <= b_given.len() {
+        (a_given, b_given)
+    } else {
+        (b_given, a_given)
+    };
+    let is_initial_of =
+        |initial: &str, name: &str| initial.chars().count() == 1 && name.starts_with(initial);
+    let mut long = long.iter();
+    short
+        .iter()
+        .all(|s| long.any(|l| s == l || is_initial_of(s, l) || is_initial_of(l, s)))
+}
+
+// Function to create a new user account. The first account on a server
+// becomes an admin and later ones get `role`; checking for other accounts in
+// the insert itself keeps concurrent first registrations from both becoming
+// admins.
+pub async fn create_user(
+    pool: &Pool<Sqlite>,
+    full_name: &str,
+    email: &str,
+    password_hash: &str,
+    role: Role,
+) -> Result<User, sqlx::Error> {
+    let now = Utc::now();
+    let admin = Role::Admin;
+
+    let user_id = sqlx::query!(
+        "INSERT INTO users (full_name, email, password_hash, role, created_at, updated_at) SELECT ?, ?, ?, CASE WHEN EXISTS (SELECT 1 FROM users) THEN ? ELSE ? END, ?, ?",
+        full_name,
+        email,
+        password_hash,
+        role,
+        admin,
+        now,
+        now
+    )
+    .execute(pool)
+    .await?
+    .last_insert_rowid();
+
+    get_user_by_id(pool, user_id)
+        .await?
+        .ok_or(sqlx::Error::RowNotFound)
+}
+
+// Function to get a user by email address (case-insensitive)
+pub async fn get_user_by_email(
+    pool: &Pool<Sqlite>,
+    email: &str,
+) -> Result<Option<User>, sqlx::Error> {
+    sqlx::query_as!(
+        User,
+        "SELECT id as \"id!\", full_name, email, password_hash, role as \"role: Role\", disabled, created_at as \"created_at: DateTime<Utc>\", totp_enabled_at as \"totp_enabled_at: DateTime<Utc>\" FROM users WHERE email = ?",
+        email
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+// Function to get a user by ID
+pub async fn get_user_by_id(
+    pool: &Pool<Sqlite>,
+    user_id: i64,
+) -> Result<Option<User>, sqlx::Error> {
+    sqlx::query_as!(
+        User,
+        "SELECT id as \"id!\", full_name, email, password_hash, role as \"role: Role\", disabled, created_at as \"created_at: DateTime<Utc>\", totp_enabled_at as \"totp_enabled_at: DateTime<Utc>\" FROM users WHERE id = ?",
+        user_id
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+// Function to list all users
+pub async fn list_users(pool: &Pool<Sqlite>) -> Result<Vec<User>, sqlx::Error> {
+    sqlx::query_as!(
+        User,
+        "SELECT id as \"id!\", full_name, email, password_hash, role as \"role: Role\", disabled, created_at as \"created_at: DateTime<Utc>\", totp_enabled_at as \"totp_enabled_at: DateTime<Utc>\" FROM users ORDER BY id"
+    )
+    .fetch_all(pool)
+    .await
+}
+
+// Function to change a user's role or disabled flag
+pub async fn update_user(
+    pool: &Pool<Sqlite>,
+    user_id: i64,
+    request: UpdateUserRequest,
+) -> Result<Option<User>, sqlx::Error> {
+    let Some(user) = get_user_by_id(pool, user_id).await? else {
+        return Ok(None); // User doesn't exist
+    };
+
+    let now = Utc::now();
+    let role = request.role.unwrap_or(user.role);
+    let disabled = request.disabled.unwrap_or(user.disabled);
+
+    sqlx::query!(
+        "UPDATE users SET role = ?, disabled = ?, updated_at = ? WHERE id = ?",
+        role,
+        disabled,
+        now,
+        user_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(Some(User {
+        role,
+        disabled,
+        ..user
+    }))
+}
+
+// Function to start a login session identified by a hashed refresh token or session cookie
+pub async fn create_session(
+    pool: &Pool<Sqlite>,
+    session_id: &str,
+    user_id: i64,
+    token_hash: &str,
+    kind: SessionKind,
+    second_factor: bool,
+    expires_at: DateTime<Utc>,
+) -> Result<(), sqlx::Error> {
+    let now = Utc::now();
+
+    sqlx::query!(
+        "INSERT INTO sessions (id, user_id, refresh_token_hash, kind, second_factor, expires_at, created_at, updated_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+        session_id,
+        user_id,
+        token_hash,
+        kind,
+        second_factor,
+        expires_at,
+        now,
+        now
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+// Function to look up the session a refresh token belongs to
+pub async fn get_session_by_refresh_token(
+    pool: &Pool<Sqlite>,
+    refresh_token_hash: &str,
+) -> Result<Option<Session>, sqlx::Error> {
+    sqlx::query_as!(
+        Session,
+        "SELECT id, user_id, expires_at as \"expires_at: DateTime<Utc>\", revoked_at as \"revoked_at: DateTime<Utc>\", second_factor FROM sessions WHERE refresh_token_hash = ? AND kind = 'api'",
+        refresh_token_hash
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+// Function to look up the web session a session cookie belongs to
+pub async fn get_web_session(
+    pool: &Pool<Sqlite>,
+    token_hash: &str,
+) -> Result<Option<Session>, sqlx::Error> {
+    sqlx::query_as!(
+        Session,
+        "SELECT id, user_id, expires_at as \"expires_at: DateTime<Utc>\", revoked_at as \"revoked_at: DateTime<Utc>\", second_factor FROM sessions WHERE refresh_token_hash = ? AND kind = 'web'",
+        token_hash
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+// Function to replace a session's refresh token, returning false if it was already used
+pub async fn rotate_session(
+    pool: &Pool<Sqlite>,
+    session_id: &str,
+    old_refresh_token_hash: &str,
+    new_refresh_token_hash: &str,
+    expires_at: DateTime<Utc>,
+) -> Result<bool, sqlx::Error> {
+    let now = Utc::now();
+
+    let result = sqlx::query!(
+        "UPDATE sessions SET refresh_token_hash = ?, expires_at = ?, updated_at = ? WHERE id = ? AND refresh_token_hash = ? AND revoked_at IS NULL",
+        new_refresh_token_hash,
+        expires_at,
+        now,
+        session_id,
+        old_refresh_token_hash
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+// Function to revoke a single session
+pub async fn revoke_session(pool: &Pool<Sqlite>, session_id: &str) -> Result<(), sqlx::Error> {
+    let now = Utc::now();
+
+    sqlx::query!(
+        "UPDATE sessions SET revoked_at = ?, updated_at = ? WHERE id = ? AND revoked_at IS NULL",
+        now,
+        now,
+        session_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+// Function to revoke every session belonging to a user
+pub async fn revoke_user_sessions(pool: &Pool<Sqlite>, user_id: i64) -> Result<(), sqlx::Error> {
+    let now = Utc::now();
+
+    sqlx::query!(
+        "UPDATE sessions SET revoked_at = ?, updated_at = ? WHERE user_id = ? AND revoked_at IS NULL",
+        now,
+        now,
+        user_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+// Function to add an access token to the revocation list
+pub async fn revoke_token(
+    pool: &Pool<Sqlite>,
+    jti: &str,
+    expires_at: DateTime<Utc>,
+) -> Result<(), sqlx::Error> {
+    let now = Utc::now();
+
+    // Entries are only needed until the token would have expired anyway
+    sqlx::query!("DELETE FROM revoked_tokens WHERE expires_at < ?", now)
+        .execute(pool)
+        .await?;
+
+    sqlx::query!(
+        "INSERT OR IGNORE INTO revoked_tokens (jti, expires_at, revoked_at) VALUES (?, ?, ?)",
+        jti,
+        expires_at,
+        now
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+// Function to check whether an access token or its session has been revoked
+pub async fn is_token_revoked(
+    pool: &Pool<Sqlite>,
+    jti: &str,
+    session_id: &str,
+) -> Result<bool, sqlx::Error> {
+    let revoked = sqlx::query!(
+        "SELECT EXISTS(SELECT 1 FROM revoked_tokens WHERE jti = ?) OR EXISTS(SELECT 1 FROM sessions WHERE id = ? AND revoked_at IS NOT NULL) as \"revoked!: bool\"",
+        jti,
+        session_id
+    )
+    .fetch_one(pool)
+    .await?
+    .revoked;
+
+    Ok(revoked)
+}
+
+// Function to store a new API key
+pub async fn create_api_key(
+    pool: &Pool<Sqlite>,
+    user_id: i64,
+    prefix: &str,
+    key_hash: &str,
+    request: CreateApiKeyRequest,
+) -> Result<ApiKey, sqlx::Error> {
+    let now = Utc::now();
+    let scopes = Scope::join(&request.scopes);
+
+    let api_key_id = sqlx::query!(
+        "INSERT INTO api_keys (user_id, name, prefix, key_hash, scopes, expires_at, created_at) VALUES (?, ?, ?, ?, ?, ?, ?)",
+        user_id,
+        request.name,
+        prefix,
+        key_hash,
+        scopes,
+        request.expires_at,
+        now
+    )
+    .execute(pool)
+    .await?
+    .last_insert_rowid();
+
+    Ok(ApiKey {
+        id: api_key_id,
+        user_id,
+        name: request.name,
+        prefix: prefix.to_string(),
+        scopes,
+        expires_at: request.expires_at,
+        last_used_at: None,
+        revoked_at: None,
+        created_at: now,
+    })
+}
+
+// Function to list a user's API keys
+pub async fn list_api_keys(pool: &Pool<Sqlite>, user_id: i64) -> Result<Vec<ApiKey>, sqlx::Error> {
+    sqlx::query_as!(
+        ApiKey,
+        "SELECT id as \"id!\", user_id, name, prefix, scopes, expires_at as \"expires_at: DateTime<Utc>\", last_used_at as \"last_used_at: DateTime<Utc>\", revoked_at as \"revoked_at: DateTime<Utc>\", created_at as \"created_at: DateTime<Utc>\" FROM api_keys WHERE user_id = ? ORDER BY id",
+        user_id
+    )
+    .fetch_all(pool)
+    .await
+}
+
+// Function to look up an API key by the hash of its value
+pub async fn get_api_key_by_hash(
+    pool: &Pool<Sqlite>,
+    key_hash: &str,
+) -> Result<Option<ApiKey>, sqlx::Error> {
+    sqlx::query_as!(
+        ApiKey,
+        "SELECT id as \"id!\", user_id, name, prefix, scopes, expires_at as \"expires_at: DateTime<Utc>\", last_used_at as \"last_used_at: DateTime<Utc>\", revoked_at as \"revoked_at: DateTime<Utc>\", created_at as \"created_at: DateTime<Utc>\" FROM api_keys WHERE key_hash = ?",
+        key_hash
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+// Function to record that an API key was used
+pub async fn touch_api_key(pool: &Pool<Sqlite>, api_key_id: i64) -> Result<(), sqlx::Error> {
+    let now = Utc::now();
+
+    sqlx::query!(
+        "UPDATE api_keys SET last_used_at = ? WHERE id = ?",
+        now,
+        api_key_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+// Function to revoke an API key, optionally restricted to one owner
+pub async fn revoke_api_key(
+    pool: &Pool<Sqlite>,
+    api_key_id: i64,
+    owner_id: Option<i64>,
+) -> Result<bool, sqlx::Error> {
+    let now = Utc::now();
+
+    let result = sqlx::query!(
+        "UPDATE api_keys SET revoked_at = COALESCE(revoked_at, ?) WHERE id = ? AND (? IS NULL OR user_id = ?)",
+        now,
+        api_key_id,
+        owner_id,
+        owner_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+// Function to find the user linked to an OpenID Connect identity
+pub async fn get_user_by_identity(
+    pool: &Pool<Sqlite>,
+    issuer: &str,
+    subject: &str,
+) -> Result<Option<User>, sqlx::Error> {
+    sqlx::query_as!(
+        User,
+        "SELECT users.id as \"id!\", users.full_name, users.email, users.password_hash, users.role as \"role: Role\", users.disabled, users.created_at as \"created_at: DateTime<Utc>\", users.totp_enabled_at as \"totp_enabled_at: DateTime<Utc>\" FROM users JOIN user_identities ON user_identities.user_id = users.id WHERE user_identities.issuer = ? AND user_identities.subject = ?",
+        issuer,
+        subject
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+// Function to link an OpenID Connect identity to a user
+pub async fn link_identity(
+    pool: &Pool<Sqlite>,
+    user_id: i64,
+    issuer: &str,
+    subject: &str,
+    email: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    let now = Utc::now();
+
+    sqlx::query!(
+        "INSERT INTO user_identities (user_id, issuer, subject, email, created_at, last_login_at) VALUES (?, ?, ?, ?, ?, ?)",
+        user_id,
+        issuer,
+        subject,
+        email,
+        now,
+        now
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+// Function to record a login through an OpenID Connect identity
+pub async fn touch_identity(
+    pool: &Pool<Sqlite>,
+    issuer: &str,
+    subject: &str,
+    email: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    let now = Utc::now();
+
+    sqlx::query!(
+        "UPDATE user_identities SET last_login_at = ?, email = COALESCE(?, email) WHERE issuer = ? AND subject = ?",
+        now,
+        email,
+        issuer,
+        subject
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+// Function to add a login or registration attempt to the audit log
+pub async fn record_login_attempt(
+    pool: &Pool<Sqlite>,
+    endpoint: &str,
+    ip: &str,
+    email: Option<&str>,
+    succeeded: bool,
+    reason: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    let now = Utc::now();
+
+    sqlx::query!(
+        "INSERT INTO login_attempts (endpoint, ip, email, succeeded, reason, created_at) VALUES (?, ?, ?, ?, ?, ?)",
+        endpoint,
+        ip,
+        email,
+        succeeded,
+        reason,
+        now
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+// Function to count failed attempts from an address since a given time
+pub async fn failures_by_ip(
+    pool: &Pool<Sqlite>,
+    ip: &str,
+    since: DateTime<Utc>,
+) -> Result<AttemptStats, sqlx::Error> {
+    // Compare as Julian days, as stored timestamps are not all formatted alike
+    sqlx::query_as!(
+        AttemptStats,
+        "SELECT COUNT(*) as \"failures!: i64\", MAX(created_at) as \"last_failure: DateTime<Utc>\" FROM login_attempts WHERE ip = ? AND succeeded = 0 AND julianday(created_at) >= julianday(?)",
+        ip,
+        since
+    )
+    .fetch_one(pool)
+    .await
+}
+
+// Function to count failed attempts on an account since a given time and its last successful login
+pub async fn failures_by_email(
+    pool: &Pool<Sqlite>,
+    email: &str,
+    since: DateTime<Utc>,
+) -> Result<AttemptStats, sqlx::Error> {
+    // Compare as Julian days, as stored timestamps are not all formatted alike
+    sqlx::query_as!(
+        AttemptStats,
+        "SELECT COUNT(*) as \"failures!: i64\", MAX(created_at) as \"last_failure: DateTime<Utc>\" FROM login_attempts WHERE email = ? AND succeeded = 0 AND julianday(created_at) >= julianday(?) AND julianday(created_at) > COALESCE((SELECT MAX(julianday(created_at)) FROM login_attempts WHERE email = ? AND succeeded = 1), 0)",
+        email,
+        since,
+        email
+    )
+    .fetch_one(pool)
+    .await
+}
+
+// Function to store a new TOTP secret awaiting confirmation, unless TOTP is already enabled
+pub async fn set_totp_secret(
+    pool: &Pool<Sqlite>,
+    user_id: i64,
+    secret: &str,
+) -> Result<bool, sqlx::Error> {
+    let now = Utc::now();
+
+    let result = sqlx::query!(
+        "UPDATE users SET totp_secret = ?, totp_last_step = NULL, updated_at = ? WHERE id = ? AND totp_enabled_at IS NULL",
+        secret,
+        now,
+        user_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+// Function to get a user's TOTP secret, enrolled or pending confirmation
+pub async fn get_totp_secret(
+    pool: &Pool<Sqlite>,
+    user_id: i64,
+) -> Result<Option<String>, sqlx::Error> {
+    let secret = sqlx::query!("SELECT totp_secret FROM users WHERE id = ?", user_id)
+        .fetch_optional(pool)
+        .await?
+        .and_then(|row| row.totp_secret);
+    Ok(secret)
+}
+
+// Function to mark a TOTP time step as used, failing if it or a later one was used already
+pub async fn use_totp_step(
+    pool: &Pool<Sqlite>,
+    user_id: i64,
+    step: i64,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        "UPDATE users SET totp_last_step = ? WHERE id = ? AND (totp_last_step IS NULL OR totp_last_step < ?)",
+        step,
+        user_id,
+        step
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+// Function to enable TOTP for a user, replacing their recovery codes
+pub async fn enable_totp(
+    pool: &Pool<Sqlite>,
+    user_id: i64,
+    recovery_code_hashes: &[String],
+) -> Result<(), sqlx::Error> {
+    let now = Utc::now();
+    let mut tx = pool.begin().await?;
+
+    sqlx::query!("DELETE FROM recovery_codes WHERE user_id = ?", user_id)
+        .execute(&mut *tx)
+        .await?;
+
+    for code_hash in recovery_code_hashes {
+        sqlx::query!(
+            "INSERT INTO recovery_codes (user_id, code_hash, created_at) VALUES (?, ?, ?)",
+            user_id,
+            code_hash,
+            now
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    sqlx::query!(
+        "UPDATE users SET totp_enabled_at = ?, updated_at = ? WHERE id = ?",
+        now,
+        now,
+        user_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(())
+}
+
+// Function to turn off TOTP for a user and drop their recovery codes
+pub async fn disable_totp(pool: &Pool<Sqlite>, user_id: i64) -> Result<(), sqlx::Error> {
+    let now = Utc::now();
+    let mut tx = pool.begin().await?;
+
+    sqlx::query!(
+        "UPDATE users SET totp_secret = NULL, totp_enabled_at = NULL, totp_last_step = NULL, updated_at = ? WHERE id = ?",
+        now,
+        user_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!("DELETE FROM recovery_codes WHERE user_id = ?", user_id)
+        .execute(&mut *tx)
+        .await?;
+
+    sqlx::query!("DELETE FROM login_challenges WHERE user_id = ?", user_id)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+    Ok(())
+}
+
+// Function to use up a recovery code, returning whether it was valid and unused
+pub async fn use_recovery_code(
+    pool: &Pool<Sqlite>,
+    user_id: i64,
+    code_hash: &str,
+) -> Result<bool, sqlx::Error> {
+    let now = Utc::now();
+
+    let result = sqlx::query!(
+        "UPDATE recovery_codes SET used_at = ? WHERE user_id = ? AND code_hash = ? AND used_at IS NULL",
+        now,
+        user_id,
+        code_hash
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+// Function to count the recovery codes a user has left
+pub async fn count_recovery_codes(pool: &Pool<Sqlite>, user_id: i64) -> Result<i64, sqlx::Error> {
+    let count = sqlx::query!(
+        "SELECT COUNT(*) as count FROM recovery_codes WHERE user_id = ? AND used_at IS NULL",
+        user_id
+    )
+    .fetch_one(pool)
+    .await?
+    .count;
+    Ok(count)
+}
+
+// Function to store a login that passed the password check and awaits its second factor
+pub async fn create_login_challenge(
+    pool: &Pool<Sqlite>,
+    token_hash: &str,
+    user_id: i64,
+    expires_at: DateTime<Utc>,
+) -> Result<(), sqlx::Error> {
+    let now = Utc::now();
+
+    // Forget challenges nobody completed
+    sqlx::query!("DELETE FROM login_challenges WHERE expires_at < ?", now)
+        .execute(pool)
+        .await?;
+
+    sqlx::query!(
+        "INSERT INTO login_challenges (token_hash, user_id, expires_at, created_at) VALUES (?, ?, ?, ?)",
+        token_hash,
+        user_id,
+        expires_at,
+        now
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+// Function to look up a pending login by its challenge token
+pub async fn get_login_challenge(
+    pool: &Pool<Sqlite>,
+    token_hash: &str,
+) -> Result<Option<LoginChallenge>, sqlx::Error> {
+    sqlx::query_as!(
+        LoginChallenge,
+        "SELECT user_id, expires_at as \"expires_at: DateTime<Utc>\" FROM login_challenges WHERE token_hash = ?",
+        token_hash
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+// Function to remove a completed login challenge, returning whether it was still pending
+pub async fn delete_login_challenge(
+    pool: &Pool<Sqlite>,
+    token_hash: &str,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        "DELETE FROM login_challenges WHERE token_hash = ?",
+        token_hash
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
 }
@@ -0,0 +1,442 @@
+//! OpenID Connect login module for the Quotes Server.
+//!
+//! Implements the authorization-code flow with PKCE against an external identity
+//! provider: discovery, the authorization redirect, the code exchange and ID token
+//! verification. Verified identities are mapped to local user accounts, which then
+//! receive the server's own tokens.
+//!
+use crate::authjwt::{self, AuthError, Role, read_secret};
+use crate::db::{self, User};
+use axum::{
+    Json,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use chrono::{DateTime, TimeDelta, Utc};
+use jsonwebtoken::{Algorithm, DecodingKey, Validation, decode, decode_header, jwk::JwkSet};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use sqlx::SqlitePool;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, RwLock},
+    time::Duration,
+};
+use tokio::sync::OnceCell;
+
+/// How long a started login may take before its state is discarded
+const LOGIN_TTL: TimeDelta = TimeDelta::minutes(10);
+
+/// Name of the cookie binding a login's `state` to the browser that started it
+pub const STATE_COOKIE: &str = "oidc_state";
+
+/// ID token signature algorithms accepted from the identity provider
+const ALLOWED_ALGORITHMS: [Algorithm; 7] = [
+    Algorithm::RS256,
+    Algorithm::RS384,
+    Algorithm::RS512,
+    Algorithm::PS256,
+    Algorithm::ES256,
+    Algorithm::ES384,
+    Algorithm::EdDSA,
+];
+
+/// Identity provider settings
+#[derive(Clone, Debug)]
+pub struct OidcConfig {
+    /// Issuer URL; discovery is fetched from `<issuer>/.well-known/openid-configuration`
+    pub issuer: String,
+    pub client_id: String,
+    /// Client secret, sent with the code exchange when set
+    pub client_secret: Option<String>,
+    /// Our callback URL as registered with the provider
+    pub redirect_url: String,
+}
+
+impl OidcConfig {
+    /// Read the settings from `OIDC_ISSUER`, `OIDC_CLIENT_ID`, `OIDC_REDIRECT_URL`
+    /// and the optional `OIDC_CLIENT_SECRET` file. Returns `None` when no issuer
+    /// is configured.
+    pub async fn from_env() -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        let Ok(issuer) = std::env::var("OIDC_ISSUER") else {
+            return Ok(None);
+        };
+        let client_id = std::env::var("OIDC_CLIENT_ID").map_err(|_| "OIDC_CLIENT_ID not set")?;
+        let redirect_url =
+            std::env::var("OIDC_REDIRECT_URL").map_err(|_| "OIDC_REDIRECT_URL not set")?;
+        let client_secret = match std::env::var("OIDC_CLIENT_SECRET") {
+            Ok(_) => Some(read_secret("OIDC_CLIENT_SECRET", "").await?),
+            Err(_) => None,
+        };
+
+        Ok(Some(Self {
+            issuer,
+            client_id,
+            client_secret,
+            redirect_url,
+        }))
+    }
+}
+
+/// Subset of the provider's discovery document we rely on
+#[derive(Debug, Deserialize)]
+struct ProviderMetadata {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+/// Claims read from a verified ID token
+#[derive(Debug, Deserialize)]
+pub struct IdTokenClaims {
+    pub sub: String,
+    pub nonce: Option<String>,
+    pub email: Option<String>,
+    #[serde(default)]
+    pub email_verified: bool,
+    pub name: Option<String>,
+}
+
+/// A login that was redirected to the provider and has not come back yet
+struct PendingLogin {
+    code_verifier: String,
+    nonce: String,
+    /// Web UI page to return to; API logins receive tokens instead
+    return_to: Option<String>,
+    expires_at: DateTime<Utc>,
+}
+
+/// A finished login
+pub struct OidcLogin {
+    pub claims: IdTokenClaims,
+    pub return_to: Option<String>,
+}
+
+/// OpenID Connect client shared by all requests
+#[derive(Clone)]
+pub struct OidcClient {
+    config: Arc<OidcConfig>,
+    http: reqwest::Client,
+    metadata: Arc<OnceCell<ProviderMetadata>>,
+    jwks: Arc<RwLock<JwkSet>>,
+    pending: Arc<Mutex<HashMap<String, PendingLogin>>>,
+}
+
+impl OidcClient {
+    /// Client for the given provider; discovery runs on first use
+    pub fn new(config: OidcConfig) -> Self {
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()
+            .expect("HTTP client configuration is valid");
+
+        Self {
+            config: Arc::new(config),
+            http,
+            metadata: Arc::new(OnceCell::new()),
+            jwks: Arc::new(RwLock::new(JwkSet { keys: Vec::new() })),
+            pending: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub fn issuer(&self) -> &str {
+        &self.config.issuer
+    }
+
+    /// Whether the state cookie should be restricted to HTTPS
+    pub fn secure_cookies(&self) -> bool {
+        self.config.redirect_url.starts_with("https://")
+    }
+
+    /// Fetch the discovery document once; failures are retried on the next login
+    async fn metadata(&self) -> Result<&ProviderMetadata, OidcError> {
+        self.metadata
+            .get_or_try_init(|| async {
+                let url = format!(
+                    "{}/.well-known/openid-configuration",
+                    self.config.issuer.trim_end_matches('/')
+                );
+                let metadata: ProviderMetadata = self
+                    .http
+                    .get(url)
+                    .send()
+                    .await
+                    .and_then(reqwest::Response::error_for_status)
+                    .map_err(|err| OidcError::Provider(err.to_string()))?
+                    .json()
+                    .await
+                    .map_err(|err| OidcError::Provider(err.to_string()))?;
+
+                if metadata.issuer != self.config.issuer {
+                    return Err(OidcError::Provider(format!(
+                        "discovery document names issuer {}",
+                        metadata.issuer
+                    )));
+                }
+                Ok(metadata)
+            })
+            .await
+    }
+
+    /// Start a login, returning the provider URL to redirect to and the `state`
+    /// value to bind to the browser. Web UI logins pass the page to return to.
+    pub async fn authorization_url(
+        &self,
+        return_to: Option<String>,
+    ) -> Result<(String, String), OidcError> {
+        let metadata = self.metadata().await?;
+
+        let state = authjwt::random_token(16);
+        let nonce = authjwt::random_token(16);
+        let code_verifier = authjwt::random_token(32);
+        let code_challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()));
+
+        let url = reqwest::Url::parse_with_params(
+            &metadata.authorization_endpoint,
+            [
+                ("response_type", "code"),
+                ("client_id", self.config.client_id.as_str()),
+                ("redirect_uri", self.config.redirect_url.as_str()),
+                ("scope", "openid email profile"),
+                ("state", state.as_str()),
+                ("nonce", nonce.as_str()),
+                ("code_challenge", code_challenge.as_str()),
+                ("code_challenge_method", "S256"),
+            ],
+        )
+        .map_err(|err| OidcError::Provider(err.to_string()))?;
+
+        let now = Utc::now();
+        let mut pending = self.pending.lock().unwrap();
+        pending.retain(|_, login| login.expires_at > now);
+        pending.insert(
+            state.clone(),
+            PendingLogin {
+                code_verifier,
+                nonce,
+                return_to,
+                expires_at: now + LOGIN_TTL,
+            },
+        );
+
+        Ok((url.into(), state))
+    }
+
+    /// Finish a login: redeem the authorization code and verify the ID token.
+    /// Each `state` can be used once.
+    pub async fn exchange(&self, code: &str, state: &str) -> Result<OidcLogin, OidcError> {
+        let login = self
+            .pending
+            .lock()
+            .unwrap()
+            .remove(state)
+            .filter(|login| login.expires_at > Utc::now())
+            .ok_or(OidcError::InvalidState)?;
+        let metadata = self.metadata().await?;
+
+        let mut form = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", self.config.redirect_url.as_str()),
+            ("client_id", self.config.client_id.as_str()),
+            ("code_verifier", login.code_verifier.as_str()),
+        ];
+        if let Some(secret) = &self.config.client_secret {
+            form.push(("client_secret", secret.as_str()));
+        }
+
+        let response: TokenResponse = self
+            .http
+            .post(&metadata.token_endpoint)
+            .form(&form)
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .map_err(|err| OidcError::Provider(err.to_string()))?
+            .json()
+            .await
+            .map_err(|err| OidcError::Provider(err.to_string()))?;
+
+        let claims = self
+            .verify_id_token(&response.id_token, &login.nonce)
+            .await?;
+        Ok(OidcLogin {
+            claims,
+            return_to: login.return_to,
+        })
+    }
+
+    /// Check an ID token's signature against the provider's JWKS, plus its
+    /// issuer, audience, expiry and nonce
+    async fn verify_id_token(&self, token: &str, nonce: &str) -> Result<IdTokenClaims, OidcError> {
+        let header = decode_header(token).map_err(|_| OidcError::InvalidIdToken)?;
+        if !ALLOWED_ALGORITHMS.contains(&header.alg) {
+            return Err(OidcError::InvalidIdToken);
+        }
+
+        let key = match self.find_key(header.kid.as_deref()) {
+            Some(key) => key,
+            None => {
+                // Unknown key ID: the provider may have rotated its keys
+                self.refresh_jwks().await?;
+                self.find_key(header.kid.as_deref())
+                    .ok_or(OidcError::InvalidIdToken)?
+            }
+        };
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_issuer(&[&self.config.issuer]);
+        validation.set_audience(&[&self.config.client_id]);
+        validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+
+        let claims = decode::<IdTokenClaims>(token, &key, &validation)
+            .map_err(|_| OidcError::InvalidIdToken)?
+            .claims;
+        if claims.nonce.as_deref() != Some(nonce) {
+            return Err(OidcError::InvalidIdToken);
+        }
+        Ok(claims)
+    }
+
+    /// Look up a cached provider key, by ID or as the only key when no ID is given
+    fn find_key(&self, kid: Option<&str>) -> Option<DecodingKey> {
+        let jwks = self.jwks.read().unwrap();
+        let jwk = match kid {
+            Some(kid) => jwks.find(kid),
+            None if jwks.keys.len() == 1 => jwks.keys.first(),
+            None => None,
+        }?;
+        DecodingKey::from_jwk(jwk).ok()
+    }
+
+    async fn refresh_jwks(&self) -> Result<(), OidcError> {
+        let metadata = self.metadata().await?;
+        let jwks: JwkSet = self
+            .http
+            .get(&metadata.jwks_uri)
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .map_err(|err| OidcError::Provider(err.to_string()))?
+            .json()
+            .await
+            .map_err(|err| OidcError::Provider(err.to_string()))?;
+
+        *self.jwks.write().unwrap() = jwks;
+        Ok(())
+    }
+}
+
+/// Map a verified identity to a local user.
+///
+/// Known identities log in as the user they are linked to. Otherwise the identity
+/// is linked to the account with the same verified email address, or a new
+/// account is created for it.
+pub async fn resolve_user(
+    pool: &SqlitePool,
+    issuer: &str,
+    claims: &IdTokenClaims,
+) -> Result<User, OidcError> {
+    let email = claims
+        .email
+        .as_deref()
+        .filter(|_| claims.email_verified)
+        .map(|email| email.trim().to_lowercase());
+
+    let user = match db::get_user_by_identity(pool, issuer, &claims.sub).await {
+        Ok(Some(user)) => {
+            db::touch_identity(pool, issuer, &claims.sub, email.as_deref())
+                .await
+                .map_err(database_error)?;
+            user
+        }
+        Ok(None) => {
+            let email = email.ok_or(OidcError::UnverifiedEmail)?;
+            let user = match db::get_user_by_email(pool, &email)
+                .await
+                .map_err(database_error)?
+            {
+                Some(user) => user,
+                None => create_user(pool, claims, &email).await?,
+            };
+            db::link_identity(pool, user.id, issuer, &claims.sub, Some(&email))
+                .await
+                .map_err(database_error)?;
+            user
+        }
+        Err(err) => return Err(database_error(err)),
+    };
+
+    if user.disabled {
+        return Err(OidcError::Auth(AuthError::AccountDisabled));
+    }
+    Ok(user)
+}
+
+/// Create an account for a new identity. It has no usable password; the
+/// first account on a server becomes an admin, like with registration.
+async fn create_user(
+    pool: &SqlitePool,
+    claims: &IdTokenClaims,
+    email: &str,
+) -> Result<User, OidcError> {
+    let full_name = claims
+        .name
+        .as_deref()
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .unwrap_or(email);
+
+    db::create_user(pool, full_name, email, "", Role::Contributor)
+        .await
+        .map_err(database_error)
+}
+
+fn database_error(err: sqlx::Error) -> OidcError {
+    eprintln!("Database error: {}", err);
+    OidcError::Auth(AuthError::Database)
+}
+
+/// OpenID Connect login errors
+#[derive(Debug)]
+pub enum OidcError {
+    NotConfigured,
+    Provider(String),
+    InvalidState,
+    InvalidIdToken,
+    UnverifiedEmail,
+    Auth(AuthError),
+}
+
+impl IntoResponse for OidcError {
+    fn into_response(self) -> Response {
+        let (status, error_message) = match self {
+            OidcError::NotConfigured => (
+                StatusCode::NOT_FOUND,
+                "OpenID Connect login is not configured",
+            ),
+            OidcError::Provider(err) => {
+                eprintln!("Identity provider error: {}", err);
+                (StatusCode::BAD_GATEWAY, "Identity provider request failed")
+            }
+            OidcError::InvalidState => (StatusCode::BAD_REQUEST, "Unknown or expired login"),
+            OidcError::InvalidIdToken => (StatusCode::UNAUTHORIZED, "Invalid ID token"),
+            OidcError::UnverifiedEmail => (
+                StatusCode::FORBIDDEN,
+                "Identity provider did not supply a verified email address",
+            ),
+            OidcError::Auth(err) => return err.into_response(),
+        };
+        let body = Json(serde_json::json!({
+            "error": error_message,
+        }));
+        (status, body).into_response()
+    }
+}
@@ -2,20 +2,136 @@
 //!
 //! Defines Askama template structs for rendering HTML pages and implements
 //! conversion from template objects into HTTP responses.
+//! Also serves the login and logout forms backing the web UI's session cookie,
+//! the two-factor authentication pages, the forms for creating, editing and
+//! deleting quotes, the list of the signed-in user's own quotes, the tag
+//! cloud, and the author pages.
 //!
 use crate::AppState;
-use crate::api::SearchParams;
-use crate::db::{self, QuoteWithTags};
+use crate::api::{
+    MAX_PAGE_SIZE, PageOffsets, QuoteSort, SearchParams, local_path, validate_citation,
+    validate_quote,
+};
+use crate::authjwt::{self, AuthError, CsrfToken, Role};
+use crate::db::{
+    self, Author, Citation, CreateQuoteRequest, QuoteWithTags, TagWithCount, UpdateQuoteRequest,
+    UserProfile,
+};
+use crate::throttle::{self, ClientIp};
+use crate::totp::{self, TotpEnrollment};
 use askama::Template;
 use axum::{
-    extract::{Query, State},
-    http::StatusCode,
-    response::{Html, IntoResponse, Response},
+    Form,
+    extract::{FromRequestParts, Path, Query, State},
+    http::{HeaderValue, StatusCode, header, request::Parts},
+    response::{Html, IntoResponse, Redirect, Response},
 };
+use serde::Deserialize;
+use std::convert::Infallible;
+
+/// Signed-in user and CSRF token shared by every page
+#[derive(Debug, Default)]
+pub struct PageContext {
+    pub user: Option<UserProfile>,
+    /// Role the session acts with
+    pub role: Option<Role>,
+    /// Whether the session passes [`authjwt::Claims::check_second_factor`],
+    /// which editors and admins need to delete quotes
+    pub second_factor: bool,
+    pub csrf_token: String,
+}
+
+impl PageContext {
+    fn has_role(&self, role: Role) -> bool {
+        self.role.is_some_and(|own| own >= role)
+    }
+
+    /// Whether the user may add quotes
+    pub fn can_create(&self) -> bool {
+        self.has_role(Role::Contributor)
+    }
+
+    /// Whether the user may edit and delete any quote
+    pub fn can_edit(&self) -> bool {
+        self.has_role(Role::Editor)
+    }
+
+    /// Whether the user may edit and delete this quote: editors may change any
+    /// quote, contributors only their own
+    pub fn can_modify(&self, quote: &QuoteWithTags) -> bool {
+        self.can_edit()
+            || (self.can_create()
+                && self
+                    .user
+                    .as_ref()
+                    .is_some_and(|user| quote.created_by == Some(user.id)))
+    }
+
+    /// Response refusing to delete quotes for editors and admins without
+    /// two-factor authentication, see [`authjwt::Claims::check_second_factor`]
+    fn deny_without_second_factor(&self) -> Option<Response> {
+        if self.can_edit() && !self.second_factor {
+            return Some(AuthError::TotpRequired.into_response());
+        }
+        None
+    }
+
+    /// Response replacing the page at `path` for users below `role`: anonymous
+    /// visitors are sent to the login page, others are refused
+    fn deny_unless(&self, role: Role, path: &str) -> Option<Response> {
+        match &self.user {
+            None => Some(Redirect::to(&format!("/login?next={path}")).into_response()),
+            Some(_) if !self.has_role(role) => {
+                Some((StatusCode::FORBIDDEN, "Insufficient permissions").into_response())
+            }
+            Some(_) => None,
+        }
+    }
+}
+
+impl FromRequestParts<AppState> for PageContext {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let csrf_token = parts
+            .extensions
+            .get::<CsrfToken>()
+            .map(|token| token.0.clone())
+            .unwrap_or_default();
+
+        // Pages render for anonymous visitors when the cookie is missing or stale
+        let mut user = None;
+        let mut role = None;
+        let mut second_factor = false;
+        if let Some(token) = authjwt::cookie(&parts.headers, authjwt::SESSION_COOKIE)
+            && let Ok(claims) = authjwt::authenticate_web_session(&state.pool, token).await
+            && let Ok(user_id) = claims.user_id()
+        {
+            user = db::get_user_by_id(&state.pool, user_id)
+                .await
+                .ok()
+                .flatten()
+                .map(UserProfile::from);
+            role = user.as_ref().map(|_| claims.role);
+            second_factor = claims.check_second_factor().is_ok();
+        }
+
+        Ok(Self {
+            user,
+            role,
+            second_factor,
+            csrf_token,
+        })
+    }
+}
 
 #[derive(Template)]
 #[template(path = "index.html")]
 pub struct IndexTemplate {
+    pub ctx: PageContext,
     pub active_page: String,
     pub quote: Option<QuoteWithTags>,
     pub has_quote: bool,
@@ -24,27 +140,170 @@ pub struct IndexTemplate {
 #[derive(Template)]
 #[template(path = "about.html")]
 pub struct AboutTemplate {
+    pub ctx: PageContext,
     pub active_page: String,
 }
 
 #[derive(Template)]
 #[template(path = "quotes.html")]
 pub struct QuotesTemplate {
+    pub ctx: PageContext,
     pub quotes: Vec<QuoteWithTags>,
     pub active_page: String,
+    /// Number of quotes matching the search across all pages
+    pub total: i64,
+    /// Positions of the first and last quote on this page, counting from 1
+    pub first_item: i64,
+    pub last_item: i64,
+    pub prev_url: Option<String>,
+    pub next_url: Option<String>,
+    pub q_value: String,
     pub quote_value: String,
     pub source_value: String,
     pub tag_value: String,
+    /// Sort field and direction picked, empty for the defaults
+    pub sort_value: String,
+    pub order_value: String,
+}
+
+#[derive(Template)]
+#[template(path = "my_quotes.html")]
+pub struct MyQuotesTemplate {
+    pub ctx: PageContext,
+    pub quotes: Vec<QuoteWithTags>,
+    pub active_page: String,
+}
+
+#[derive(Template)]
+#[template(path = "tags.html")]
+pub struct TagsTemplate {
+    pub ctx: PageContext,
+    pub tags: Vec<TagCloudEntry>,
+    pub active_page: String,
+}
+
+/// A tag in the tag cloud
+pub struct TagCloudEntry {
+    pub name: String,
+    pub quote_count: i64,
+    /// Link to the quotes carrying the tag
+    pub url: String,
+    /// Size from 1 to [`TAG_CLOUD_SIZES`], growing with the quote count
+    pub size: i64,
+}
+
+/// Number of font sizes in the tag cloud
+const TAG_CLOUD_SIZES: i64 = 5;
+
+impl TagCloudEntry {
+    /// Entries for the tags carried by at least one quote, sized on a
+    /// logarithmic scale so a few popular tags do not dwarf the rest
+    fn from_tags(tags: Vec<TagWithCount>) -> Vec<Self> {
+        let max = tags.iter().map(|tag| tag.quote_count).max().unwrap_or(0);
+        let scale = ((max as f64).ln()).max(f64::EPSILON);
+
+        tags.into_iter()
+            .filter(|tag| tag.quote_count > 0)
+            .map(|tag| Self {
+                url: format!("/quotes?tags={}", totp::percent_encode(&tag.name)),
+                size: 1
+                    + ((tag.quote_count as f64).ln() / scale * (TAG_CLOUD_SIZES - 1) as f64).round()
+                        as i64,
+                name: tag.name,
+                quote_count: tag.quote_count,
+            })
+            .collect()
+    }
+}
+
+#[derive(Template)]
+#[template(path = "authors.html")]
+pub struct AuthorsTemplate {
+    pub ctx: PageContext,
+    pub authors: Vec<Author>,
+    pub active_page: String,
+}
+
+#[derive(Template)]
+#[template(path = "author.html")]
+pub struct AuthorTemplate {
+    pub ctx: PageContext,
+    pub author: Author,
+    pub quotes: Vec<QuoteWithTags>,
+    pub active_page: String,
 }
 
 #[derive(Template)]
 #[template(path = "quote.html")]
 pub struct QuoteTemplate {
+    pub ctx: PageContext,
     pub quote: Option<QuoteWithTags>,
     pub has_quote: bool,
     pub active_page: String,
 }
 
+#[derive(Template)]
+#[template(path = "quote_form.html")]
+pub struct QuoteFormTemplate {
+    pub ctx: PageContext,
+    pub active_page: String,
+    pub title: String,
+    pub action: String,
+    pub quote_value: String,
+    pub source_value: String,
+    pub tags_value: String,
+    pub work_value: String,
+    pub year_value: String,
+    pub page_value: String,
+    pub url_value: String,
+    pub note_value: String,
+    pub error: Option<String>,
+}
+
+#[derive(Template)]
+#[template(path = "quote_delete.html")]
+pub struct QuoteDeleteTemplate {
+    pub ctx: PageContext,
+    pub active_page: String,
+    pub quote: QuoteWithTags,
+}
+
+#[derive(Template)]
+#[template(path = "login.html")]
+pub struct LoginTemplate {
+    pub ctx: PageContext,
+    pub active_page: String,
+    pub email_value: String,
+    pub next: String,
+    pub error: Option<String>,
+    pub oidc_enabled: bool,
+}
+
+#[derive(Template)]
+#[template(path = "login_totp.html")]
+pub struct LoginTotpTemplate {
+    pub ctx: PageContext,
+    pub active_page: String,
+    pub challenge: String,
+    pub next: String,
+    pub error: Option<String>,
+}
+
+#[derive(Template)]
+#[template(path = "totp.html")]
+pub struct TotpTemplate {
+    pub ctx: PageContext,
+    pub active_page: String,
+    pub enabled: bool,
+    /// Whether the account's role needs two-factor authentication to delete quotes
+    pub required: bool,
+    pub recovery_codes_left: i64,
+    pub enrollment: Option<TotpEnrollment>,
+    /// Recovery codes to show once, right after enabling
+    pub recovery_codes: Vec<String>,
+    pub error: Option<String>,
+}
+
 pub struct HtmlTemplate<T>(pub T);
 
 impl<T> IntoResponse for HtmlTemplate<T>
@@ -63,11 +322,12 @@ where
     }
 }
 
-pub async fn index_page(State(state): State<AppState>) -> impl IntoResponse {
+pub async fn index_page(ctx: PageContext, State(state): State<AppState>) -> impl IntoResponse {
     let quote = db::get_random_quote(&state.pool).await.unwrap_or(None);
     let has_quote = quote.is_some();
 
     let template = IndexTemplate {
+        ctx,
         active_page: "home".to_string(),
         quote,
         has_quote,
@@ -75,43 +335,125 @@ pub async fn index_page(State(state): State<AppState>) -> impl IntoResponse {
     HtmlTemplate(template)
 }
 
-pub async fn about_page() -> impl IntoResponse {
+pub async fn about_page(ctx: PageContext) -> impl IntoResponse {
     let template = AboutTemplate {
+        ctx,
         active_page: "about".to_string(),
     };
     HtmlTemplate(template)
 }
 
 pub async fn quotes_page(
+    ctx: PageContext,
     State(state): State<AppState>,
     Query(search_params): Query<SearchParams>,
 ) -> impl IntoResponse {
-    let quotes = db::search_quotes(
-        &state.pool,
-        SearchParams {
-            quote: search_params.quote.clone(),
-            source: search_params.source.clone(),
-            tag: search_params.tag.clone(),
-        },
-    )
-    .await
-    .unwrap_or_default();
+    let (quotes, total) = match db::search_quotes(&state.pool, search_params.clone()).await {
+        Ok(page) => (page.quotes, page.total),
+        Err(err) => {
+            eprintln!("Database error: {}", err);
+            (Vec::new(), 0)
+        }
+    };
 
+    let offsets = PageOffsets::new(&search_params, total);
+    let page_url = |offset: i64| format!("/quotes?{}", search_params.page_query(offset));
+    let offset = search_params.offset();
     let template = QuotesTemplate {
-        quotes,
+        ctx,
         active_page: "quotes".to_string(),
+        first_item: (offset + 1).min(total),
+        last_item: offset + quotes.len() as i64,
+        total,
+        prev_url: offsets.prev.map(page_url),
+        next_url: offsets.next.map(page_url),
+        quotes,
+        q_value: search_params.q.unwrap_or_default(),
         quote_value: search_params.quote.unwrap_or_default(),
         source_value: search_params.source.unwrap_or_default(),
         tag_value: search_params.tag.unwrap_or_default(),
+        sort_value: search_params
+            .sort
+            .map(|sort| sort.as_str())
+            .unwrap_or_default()
+            .to_string(),
+        order_value: search_params
+            .order
+            .map(|order| order.as_str())
+            .unwrap_or_default()
+            .to_string(),
     };
     HtmlTemplate(template)
 }
 
-pub async fn random_quote_page(State(state): State<AppState>) -> impl IntoResponse {
+pub async fn tags_page(ctx: PageContext, State(state): State<AppState>) -> Response {
+    match db::list_tags(&state.pool).await {
+        Ok(tags) => HtmlTemplate(TagsTemplate {
+            ctx,
+            tags: TagCloudEntry::from_tags(tags),
+            active_page: "tags".to_string(),
+        })
+        .into_response(),
+        Err(err) => database_error(err),
+    }
+}
+
+pub async fn authors_page(ctx: PageContext, State(state): State<AppState>) -> Response {
+    match db::list_authors(&state.pool).await {
+        Ok(authors) => HtmlTemplate(AuthorsTemplate {
+            ctx,
+            authors,
+            active_page: "authors".to_string(),
+        })
+        .into_response(),
+        Err(err) => database_error(err),
+    }
+}
+
+pub async fn author_page(
+    ctx: PageContext,
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+) -> Response {
+    let author = match db::get_author(&state.pool, id).await {
+        Ok(Some(author)) => author,
+        Ok(None) => {
+            return (
+                StatusCode::NOT_FOUND,
+                format!("Author with ID {} not found", id),
+            )
+                .into_response();
+        }
+        Err(err) => return database_error(err),
+    };
+
+    let search_params = SearchParams {
+        author_id: Some(id),
+        sort: Some(QuoteSort::Created),
+        limit: Some(MAX_PAGE_SIZE),
+        ..SearchParams::default()
+    };
+    match db::search_quotes(&state.pool, search_params).await {
+        Ok(page) => HtmlTemplate(AuthorTemplate {
+            ctx,
+            author,
+            quotes: page.quotes,
+            active_page: "authors".to_string(),
+        })
+        .into_response(),
+        Err(err) => database_error(err),
+    }
+}
+
+pub async fn random_quote_page(
+    ctx: PageContext,
+    State(state): State<AppState>,
+) -> impl IntoResponse {
     let quote = db::get_random_quote(&state.pool).await.unwrap_or(None);
 
     let has_quote = quote.is_some();
     let template = QuoteTemplate {
+        ctx,
         quote,
         has_quote,
         active_page: "random".to_string(),
@@ -119,6 +461,694 @@ pub async fn random_quote_page(State(state): State<AppState>) -> impl IntoRespon
     HtmlTemplate(template)
 }
 
+#[derive(Debug, Deserialize)]
+pub struct LoginParams {
+    pub next: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LoginForm {
+    pub email: String,
+    pub password: String,
+    pub next: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LoginTotpParams {
+    pub challenge: String,
+    pub next: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LoginTotpForm {
+    pub challenge: String,
+    pub code: String,
+    pub next: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TotpForm {
+    pub code: String,
+}
+
+pub async fn login_page(
+    ctx: PageContext,
+    State(state): State<AppState>,
+    Query(params): Query<LoginParams>,
+) -> Response {
+    let next = local_path(params.next.as_deref().unwrap_or("/")).to_string();
+    if ctx.user.is_some() {
+        return Redirect::to(&next).into_response();
+    }
+
+    let template = LoginTemplate {
+        ctx,
+        active_page: "login".to_string(),
+        email_value: String::new(),
+        next,
+        error: None,
+        oidc_enabled: state.oidc.is_some(),
+    };
+    HtmlTemplate(template).into_response()
+}
+
+pub async fn login_submit(
+    ctx: PageContext,
+    State(state): State<AppState>,
+    ClientIp(ip): ClientIp,
+    Form(form): Form<LoginForm>,
+) -> Response {
+    let next = local_path(form.next.as_deref().unwrap_or("/")).to_string();
+
+    let result = match throttle::authenticate_password(
+        &state.pool,
+        &state.throttle,
+        throttle::WEB_LOGIN,
+        &ip,
+        &form.email,
+        form.password,
+    )
+    .await
+    {
+        // Accounts with TOTP enabled continue on the code page
+        Ok(user) if user.totp_enabled() => match totp::start_challenge(&state.pool, &user).await {
+            Ok(challenge) => {
+                let template = LoginTotpTemplate {
+                    ctx,
+                    active_page: "login".to_string(),
+                    challenge: challenge.challenge,
+                    next,
+                    error: None,
+                };
+                return HtmlTemplate(template).into_response();
+            }
+            Err(e) => Err(e),
+        },
+        Ok(user) => {
+            authjwt::start_web_session(&state.pool, &user, false, state.secure_cookies).await
+        }
+        Err(e) => Err(e),
+    };
+
+    match result {
+        Ok(session_cookie) => {
+            ([(header::SET_COOKIE, session_cookie)], Redirect::to(&next)).into_response()
+        }
+        Err(e) => {
+            let (status, error) = login_error(&e, "Wrong email or password.");
+            let template = LoginTemplate {
+                ctx,
+                active_page: "login".to_string(),
+                email_value: form.email,
+                next,
+                error: Some(error),
+                oidc_enabled: state.oidc.is_some(),
+            };
+            with_retry_after((status, HtmlTemplate(template)).into_response(), &e)
+        }
+    }
+}
+
+/// Status and message shown on the login pages for a failed login
+fn login_error(error: &AuthError, wrong_credentials: &str) -> (StatusCode, String) {
+    match error {
+        AuthError::MissingCredentials => (
+            StatusCode::BAD_REQUEST,
+            "Please enter your email and password.".to_string(),
+        ),
+        AuthError::WrongCredentials => (StatusCode::UNAUTHORIZED, wrong_credentials.to_string()),
+        AuthError::AccountDisabled => (
+            StatusCode::FORBIDDEN,
+            "This account has been disabled.".to_string(),
+        ),
+        AuthError::InvalidToken | AuthError::TokenExpired => (
+            StatusCode::BAD_REQUEST,
+            "Your login has expired, please log in again.".to_string(),
+        ),
+        AuthError::TooManyAttempts(secs) => (
+            StatusCode::TOO_MANY_REQUESTS,
+            format!("Too many failed attempts. Please try again in {secs} seconds."),
+        ),
+        _ => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Login failed, please try again.".to_string(),
+        ),
+    }
+}
+
+/// Tell locked out clients when to come back, as the API does
+fn with_retry_after(mut response: Response, error: &AuthError) -> Response {
+    if let AuthError::TooManyAttempts(secs) = error {
+        response
+            .headers_mut()
+            .insert(header::RETRY_AFTER, HeaderValue::from(*secs));
+    }
+    response
+}
+
+pub async fn login_totp_page(ctx: PageContext, Query(params): Query<LoginTotpParams>) -> Response {
+    let template = LoginTotpTemplate {
+        ctx,
+        active_page: "login".to_string(),
+        challenge: params.challenge,
+        next: local_path(params.next.as_deref().unwrap_or("/")).to_string(),
+        error: None,
+    };
+    HtmlTemplate(template).into_response()
+}
+
+pub async fn login_totp_submit(
+    ctx: PageContext,
+    State(state): State<AppState>,
+    ClientIp(ip): ClientIp,
+    Form(form): Form<LoginTotpForm>,
+) -> Response {
+    let next = local_path(form.next.as_deref().unwrap_or("/")).to_string();
+
+    let result = match throttle::complete_challenge(
+        &state.pool,
+        &state.throttle,
+        throttle::WEB_TOTP,
+        &ip,
+        &form.challenge,
+        &form.code,
+    )
+    .await
+    {
+        Ok(user) => {
+            authjwt::start_web_session(&state.pool, &user, true, state.secure_cookies).await
+        }
+        Err(e) => Err(e),
+    };
+
+    let error = match result {
+        Ok(session_cookie) => {
+            return ([(header::SET_COOKIE, session_cookie)], Redirect::to(&next)).into_response();
+        }
+        Err(e) => e,
+    };
+
+    let (status, message) = login_error(&error, "Wrong code, please try again.");
+    let response = match error {
+        // The challenge stays valid, so the code can be entered again
+        AuthError::WrongCredentials | AuthError::TooManyAttempts(_) => {
+            let template = LoginTotpTemplate {
+                ctx,
+                active_page: "login".to_string(),
+                challenge: form.challenge,
+                next,
+                error: Some(message),
+            };
+            (status, HtmlTemplate(template)).into_response()
+        }
+        _ => {
+            let template = LoginTemplate {
+                ctx,
+                active_page: "login".to_string(),
+                email_value: String::new(),
+                next,
+                error: Some(message),
+                oidc_enabled: state.oidc.is_some(),
+            };
+            (status, HtmlTemplate(template)).into_response()
+        }
+    };
+    with_retry_after(response, &error)
+}
+
+pub async fn logout_submit(
+    State(state): State<AppState>,
+    headers: axum::http::HeaderMap,
+) -> Response {
+    if let Some(token) = authjwt::cookie(&headers, authjwt::SESSION_COOKIE)
+        && let Ok(claims) = authjwt::authenticate_web_session(&state.pool, token).await
+        && let Err(e) = authjwt::end_session(&state.pool, &claims).await
+    {
+        return e.into_response();
+    }
+
+    (
+        [(
+            header::SET_COOKIE,
+            authjwt::clear_session_cookie(state.secure_cookies),
+        )],
+        Redirect::to("/"),
+    )
+        .into_response()
+}
+
+#[derive(Debug, Deserialize)]
+pub struct QuoteForm {
+    pub quote: String,
+    pub source: String,
+    #[serde(default)]
+    pub tags: String,
+    #[serde(default)]
+    pub work: String,
+    #[serde(default)]
+    pub year: String,
+    #[serde(default)]
+    pub page: String,
+    #[serde(default)]
+    pub url: String,
+    #[serde(default)]
+    pub note: String,
+}
+
+impl QuoteForm {
+    /// Citation entered alongside the quote, with blank fields left out
+    fn citation(&self) -> Result<Citation, &'static str> {
+        let year = match self.year.trim() {
+            "" => None,
+            year => Some(year.parse().map_err(|_| "Year must be a whole number")?),
+        };
+        validate_citation(Citation {
+            work: Some(self.work.clone()),
+            year,
+            page: Some(self.page.clone()),
+            url: Some(self.url.clone()),
+            note: Some(self.note.clone()),
+        })
+    }
+
+    /// Tags are entered as a comma-separated list
+    fn tag_list(&self) -> Vec<String> {
+        self.tags
+            .split(',')
+            .map(|tag| tag.trim().to_string())
+            .filter(|tag| !tag.is_empty())
+            .collect()
+    }
+}
+
+fn quote_not_found(id: i64) -> Response {
+    (
+        StatusCode::NOT_FOUND,
+        format!("Quote with ID {} not found", id),
+    )
+        .into_response()
+}
+
+fn database_error(err: sqlx::Error) -> Response {
+    eprintln!("Database error: {}", err);
+    (StatusCode::INTERNAL_SERVER_ERROR, "Internal server error").into_response()
+}
+
+/// Signed-in user's ID, for pages that record or check quote ownership
+fn user_id(ctx: &PageContext) -> i64 {
+    ctx.user.as_ref().map(|user| user.id).unwrap_or_default()
+}
+
+/// Load a quote the user is about to change, refusing users who may not change it
+async fn modifiable_quote(
+    ctx: &PageContext,
+    state: &AppState,
+    id: i64,
+) -> Result<QuoteWithTags, Response> {
+    match db::get_quote_by_id(&state.pool, id).await {
+        Ok(Some(quote)) if ctx.can_modify(&quote) => Ok(quote),
+        Ok(Some(_)) => Err((StatusCode::FORBIDDEN, "Insufficient permissions").into_response()),
+        Ok(None) => Err(quote_not_found(id)),
+        Err(err) => Err(database_error(err)),
+    }
+}
+
+pub async fn my_quotes_page(ctx: PageContext, State(state): State<AppState>) -> Response {
+    if let Some(response) = ctx.deny_unless(Role::Viewer, "/account/quotes") {
+        return response;
+    }
+
+    match db::get_quotes_by_creator(&state.pool, user_id(&ctx)).await {
+        Ok(quotes) => HtmlTemplate(MyQuotesTemplate {
+            ctx,
+            quotes,
+            active_page: "my-quotes".to_string(),
+        })
+        .into_response(),
+        Err(err) => database_error(err),
+    }
+}
+
+pub async fn new_quote_page(ctx: PageContext) -> Response {
+    if let Some(response) = ctx.deny_unless(Role::Contributor, "/quotes/new") {
+        return response;
+    }
+
+    let template = QuoteFormTemplate {
+        ctx,
+        active_page: "quotes".to_string(),
+        title: "Add a Quote".to_string(),
+        action: "/quotes/new".to_string(),
+        quote_value: String::new(),
+        source_value: String::new(),
+        tags_value: String::new(),
+        work_value: String::new(),
+        year_value: String::new(),
+        page_value: String::new(),
+        url_value: String::new(),
+        note_value: String::new(),
+        error: None,
+    };
+    HtmlTemplate(template).into_response()
+}
+
+pub async fn new_quote_submit(
+    ctx: PageContext,
+    State(state): State<AppState>,
+    Form(form): Form<QuoteForm>,
+) -> Response {
+    if let Some(response) = ctx.deny_unless(Role::Contributor, "/quotes/new") {
+        return response;
+    }
+
+    let result = match validate_quote(&form.quote, &form.source).and_then(|()| form.citation()) {
+        Ok(citation) => {
+            let request = CreateQuoteRequest {
+                quote: form.quote.clone(),
+                source: form.source.clone(),
+                author_id: None,
+                citation,
+                tags: Some(form.tag_list()),
+            };
+            db::create_quote(&state.pool, request, user_id(&ctx))
+                .await
+                .map(|_| ())
+                .map_err(|err| {
+                    eprintln!("Database error: {}", err);
+                    "Failed to create quote"
+                })
+        }
+        Err(message) => Err(message),
+    };
+
+    match result {
+        Ok(()) => Redirect::to("/quotes").into_response(),
+        Err(message) => {
+            let template = QuoteFormTemplate {
+                ctx,
+                active_page: "quotes".to_string(),
+                title: "Add a Quote".to_string(),
+                action: "/quotes/new".to_string(),
+                quote_value: form.quote,
+                source_value: form.source,
+                tags_value: form.tags,
+                work_value: form.work,
+                year_value: form.year,
+                page_value: form.page,
+                url_value: form.url,
+                note_value: form.note,
+                error: Some(message.to_string()),
+            };
+            (StatusCode::BAD_REQUEST, HtmlTemplate(template)).into_response()
+        }
+    }
+}
+
+pub async fn edit_quote_page(
+    ctx: PageContext,
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+) -> Response {
+    let action = format!("/quotes/{id}/edit");
+    if let Some(response) = ctx.deny_unless(Role::Contributor, &action) {
+        return response;
+    }
+
+    let quote = match modifiable_quote(&ctx, &state, id).await {
+        Ok(quote) => quote,
+        Err(response) => return response,
+    };
+
+    let template = QuoteFormTemplate {
+        ctx,
+        active_page: "quotes".to_string(),
+        title: "Edit Quote".to_string(),
+        action,
+        quote_value: quote.quote,
+        source_value: quote.source,
+        tags_value: quote.tags.join(", "),
+        work_value: quote.citation.work.unwrap_or_default(),
+        year_value: quote
+            .citation
+            .year
+            .map(|year| year.to_string())
+            .unwrap_or_default(),
+        page_value: quote.citation.page.unwrap_or_default(),
+        url_value: quote.citation.url.unwrap_or_default(),
+        note_value: quote.citation.note.unwrap_or_default(),
+        error: None,
+    };
+    HtmlTemplate(template).into_response()
+}
+
+pub async fn edit_quote_submit(
+    ctx: PageContext,
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    Form(form): Form<QuoteForm>,
+) -> Response {
+    let action = format!("/quotes/{id}/edit");
+    if let Some(response) = ctx.deny_unless(Role::Contributor, &action) {
+        return response;
+    }
+    if let Err(response) = modifiable_quote(&ctx, &state, id).await {
+        return response;
+    }
+
+    let result = match validate_quote(&form.quote, &form.source).and_then(|()| form.citation()) {
+        Ok(citation) => {
+            let request = UpdateQuoteRequest {
+                quote: form.quote.clone(),
+                source: form.source.clone(),
+                author_id: None,
+                citation,
+                tags: Some(form.tag_list()),
+            };
+            match db::update_quote(&state.pool, id, request, user_id(&ctx), None).await {
+                Ok(Some(_)) => Ok(()),
+                Ok(None) => return quote_not_found(id),
+                Err(err) => {
+                    eprintln!("Database error: {}", err);
+                    Err("Failed to update quote")
+                }
+            }
+        }
+        Err(message) => Err(message),
+    };
+
+    match result {
+        Ok(()) => Redirect::to("/quotes").into_response(),
+        Err(message) => {
+            let template = QuoteFormTemplate {
+                ctx,
+                active_page: "quotes".to_string(),
+                title: "Edit Quote".to_string(),
+                action,
+                quote_value: form.quote,
+                source_value: form.source,
+                tags_value: form.tags,
+                work_value: form.work,
+                year_value: form.year,
+                page_value: form.page,
+                url_value: form.url,
+                note_value: form.note,
+                error: Some(message.to_string()),
+            };
+            (StatusCode::BAD_REQUEST, HtmlTemplate(template)).into_response()
+        }
+    }
+}
+
+pub async fn delete_quote_page(
+    ctx: PageContext,
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+) -> Response {
+    if let Some(response) = ctx
+        .deny_unless(Role::Contributor, &format!("/quotes/{id}/delete"))
+        .or_else(|| ctx.deny_without_second_factor())
+    {
+        return response;
+    }
+
+    match modifiable_quote(&ctx, &state, id).await {
+        Ok(quote) => HtmlTemplate(QuoteDeleteTemplate {
+            ctx,
+            active_page: "quotes".to_string(),
+            quote,
+        })
+        .into_response(),
+        Err(response) => response,
+    }
+}
+
+pub async fn delete_quote_submit(
+    ctx: PageContext,
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+) -> Response {
+    if let Some(response) = ctx
+        .deny_unless(Role::Contributor, &format!("/quotes/{id}/delete"))
+        .or_else(|| ctx.deny_without_second_factor())
+    {
+        return response;
+    }
+    if let Err(response) = modifiable_quote(&ctx, &state, id).await {
+        return response;
+    }
+
+    match db::delete_quote(&state.pool, id, user_id(&ctx), None).await {
+        Ok(true) => Redirect::to("/quotes").into_response(),
+        Ok(false) => quote_not_found(id),
+        Err(err) => database_error(err),
+    }
+}
+
+/// Account of the signed-in user, for pages managing its own credentials
+async fn own_account(ctx: &PageContext, state: &AppState) -> Result<db::User, Response> {
+    let Some(profile) = &ctx.user else {
+        return Err(Redirect::to("/login?next=/account/totp").into_response());
+    };
+    match db::get_user_by_id(&state.pool, profile.id).await {
+        Ok(Some(user)) => Ok(user),
+        Ok(None) => Err(Redirect::to("/login?next=/account/totp").into_response()),
+        Err(err) => {
+            eprintln!("Database error: {}", err);
+            Err(AuthError::Database.into_response())
+        }
+    }
+}
+
+async fn totp_page_response(
+    ctx: PageContext,
+    state: &AppState,
+    user: &db::User,
+    recovery_codes: Vec<String>,
+    status: StatusCode,
+    error: Option<String>,
+) -> Response {
+    let enrollment = match totp::pending_enrollment(&state.pool, user).await {
+        Ok(enrollment) => enrollment,
+        Err(e) => return e.into_response(),
+    };
+    let recovery_codes_left = if user.totp_enabled() {
+        match db::count_recovery_codes(&state.pool, user.id).await {
+            Ok(count) => count,
+            Err(err) => {
+                eprintln!("Database error: {}", err);
+                return AuthError::Database.into_response();
+            }
+        }
+    } else {
+        0
+    };
+
+    let template = TotpTemplate {
+        ctx,
+        active_page: "account".to_string(),
+        enabled: user.totp_enabled(),
+        required: user.role >= Role::Editor,
+        recovery_codes_left,
+        enrollment,
+        recovery_codes,
+        error,
+    };
+    (status, HtmlTemplate(template)).into_response()
+}
+
+pub async fn totp_page(ctx: PageContext, State(state): State<AppState>) -> Response {
+    let user = match own_account(&ctx, &state).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+    totp_page_response(ctx, &state, &user, Vec::new(), StatusCode::OK, None).await
+}
+
+pub async fn totp_begin_submit(ctx: PageContext, State(state): State<AppState>) -> Response {
+    let user = match own_account(&ctx, &state).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+
+    match totp::begin_enrollment(&state.pool, &user).await {
+        Ok(_) | Err(AuthError::TotpAlreadyEnabled) => Redirect::to("/account/totp").into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+pub async fn totp_confirm_submit(
+    ctx: PageContext,
+    State(state): State<AppState>,
+    Form(form): Form<TotpForm>,
+) -> Response {
+    let user = match own_account(&ctx, &state).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+
+    match totp::confirm_enrollment(&state.pool, &user, &form.code).await {
+        Ok(codes) => {
+            let user = db::User {
+                totp_enabled_at: Some(chrono::Utc::now()),
+                ..user
+            };
+            totp_page_response(
+                ctx,
+                &state,
+                &user,
+                codes.recovery_codes,
+                StatusCode::OK,
+                None,
+            )
+            .await
+        }
+        Err(AuthError::WrongCredentials) => {
+            let error = Some("Wrong code, please try again.".to_string());
+            totp_page_response(
+                ctx,
+                &state,
+                &user,
+                Vec::new(),
+                StatusCode::UNAUTHORIZED,
+                error,
+            )
+            .await
+        }
+        Err(AuthError::TotpAlreadyEnabled | AuthError::TotpNotEnabled) => {
+            Redirect::to("/account/totp").into_response()
+        }
+        Err(e) => e.into_response(),
+    }
+}
+
+pub async fn totp_disable_submit(
+    ctx: PageContext,
+    State(state): State<AppState>,
+    Form(form): Form<TotpForm>,
+) -> Response {
+    let user = match own_account(&ctx, &state).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+
+    match totp::disable(&state.pool, &user, &form.code).await {
+        Ok(()) | Err(AuthError::TotpNotEnabled) => Redirect::to("/account/totp").into_response(),
+        Err(AuthError::WrongCredentials) => {
+            let error = Some("Wrong code, please try again.".to_string());
+            totp_page_response(
+                ctx,
+                &state,
+                &user,
+                Vec::new(),
+                StatusCode::UNAUTHORIZED,
+                error,
+            )
+            .await
+        }
+        Err(e) => e.into_response(),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -129,6 +1159,7 @@ mod tests {
     fn test_about_template_render() {
         // Create an about template
         let template = AboutTemplate {
+            ctx: PageContext::default(),
             active_page: "about".to_string(),
         };
 
@@ -145,6 +1176,7 @@ mod tests {
     fn test_template_into_response() {
         // Create a simple template
         let template = AboutTemplate {
+            ctx: PageContext::default(),
             active_page: "about".to_string(),
         };
 
@@ -164,13 +1196,27 @@ mod tests {
             id: 1,
             quote: "Test quote".to_string(),
             source: "Test source".to_string(),
+            author_id: None,
+            citation: Citation {
+                work: Some("Test work".to_string()),
+                year: Some(1999),
+                page: Some("p. 42".to_string()),
+                url: Some("https://example.com/test".to_string()),
+                note: None,
+            },
             created_at: now,
             updated_at: now,
+            created_by: None,
+            updated_by: None,
+            version: 1,
             tags: vec!["test".to_string()],
+            snippet: None,
+            deleted_at: None,
         };
 
         // Create a quote template with the test quote
         let template = QuoteTemplate {
+            ctx: PageContext::default(),
             quote: Some(quote),
             has_quote: true,
             active_page: "random".to_string(),
@@ -185,5 +1231,8 @@ mod tests {
         assert!(html.contains("Test quote"));
         assert!(html.contains("Test source"));
         assert!(html.contains("test")); // Tag should be present
+        assert!(html.contains(
+            "<a href=\"https://example.com/test\" rel=\"noopener noreferrer\"><cite>Test work</cite></a>, 1999, p. 42"
+        ));
     }
 }
@@ -0,0 +1,248 @@
+//! Login throttling module for the Quotes Server.
+//!
+//! Records every password login, two-factor code and registration attempt in an
+//! audit log and locks out client addresses and accounts that fail too often.
+//! Once the number of recent failures reaches the configured threshold, further
+//! attempts are refused for a lockout period that doubles with each additional
+//! failure.
+//!
+use crate::authjwt::{self, AuthError};
+use crate::db::{self, AttemptStats, User};
+use crate::totp;
+use crate::{AppState, env_or, env_time_delta};
+use axum::{
+    extract::{ConnectInfo, FromRequestParts},
+    http::request::Parts,
+};
+use chrono::{TimeDelta, Utc};
+use sqlx::SqlitePool;
+use std::{convert::Infallible, net::SocketAddr};
+use tracing::warn;
+
+/// Audit log names of the throttled endpoints
+pub const API_LOGIN: &str = "/auth";
+pub const WEB_LOGIN: &str = "/login";
+pub const REGISTER: &str = "/auth/register";
+pub const API_TOTP: &str = "/auth/totp";
+pub const WEB_TOTP: &str = "/login/totp";
+
+/// Limits applied to login and registration attempts
+#[derive(Clone, Debug)]
+pub struct ThrottleConfig {
+    /// Failed logins on one account before it is locked
+    pub max_attempts: i64,
+    /// Failed attempts from one address before it is locked
+    pub max_attempts_per_ip: i64,
+    /// Lockout after reaching a threshold, doubled for every further failure
+    pub lockout_base: TimeDelta,
+    /// Upper bound on a single lockout
+    pub lockout_max: TimeDelta,
+    /// How far back failures are counted
+    pub window: TimeDelta,
+    /// Take the client address from `X-Forwarded-For` set by a reverse proxy
+    pub trust_forwarded_for: bool,
+}
+
+impl Default for ThrottleConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            max_attempts_per_ip: 20,
+            lockout_base: TimeDelta::seconds(30),
+            lockout_max: TimeDelta::hours(1),
+            window: TimeDelta::days(1),
+            trust_forwarded_for: false,
+        }
+    }
+}
+
+impl ThrottleConfig {
+    /// Read the limits from `LOGIN_MAX_ATTEMPTS`, `LOGIN_MAX_ATTEMPTS_PER_IP`,
+    /// `LOGIN_LOCKOUT_BASE_SECS`, `LOGIN_LOCKOUT_MAX_SECS`, `LOGIN_ATTEMPT_WINDOW_SECS`
+    /// and `LOGIN_TRUST_FORWARDED_FOR`, falling back to the defaults for unset ones
+    pub fn from_env() -> Result<Self, Box<dyn std::error::Error>> {
+        let defaults = Self::default();
+        Ok(Self {
+            max_attempts: env_or("LOGIN_MAX_ATTEMPTS", defaults.max_attempts)?,
+            max_attempts_per_ip: env_or("LOGIN_MAX_ATTEMPTS_PER_IP", defaults.max_attempts_per_ip)?,
+            lockout_base: env_time_delta(
+                "LOGIN_LOCKOUT_BASE_SECS",
+                defaults.lockout_base.num_seconds(),
+                TimeDelta::try_seconds,
+            )?,
+            lockout_max: env_time_delta(
+                "LOGIN_LOCKOUT_MAX_SECS",
+                defaults.lockout_max.num_seconds(),
+                TimeDelta::try_seconds,
+            )?,
+            window: env_time_delta(
+                "LOGIN_ATTEMPT_WINDOW_SECS",
+                defaults.window.num_seconds(),
+                TimeDelta::try_seconds,
+            )?,
+            trust_forwarded_for: env_or("LOGIN_TRUST_FORWARDED_FOR", defaults.trust_forwarded_for)?,
+        })
+    }
+
+    /// Seconds left until the lockout earned by the given failures expires, if any
+    fn retry_after(&self, stats: &AttemptStats, threshold: i64) -> Option<i64> {
+        let last_failure = stats.last_failure?;
+        if stats.failures < threshold {
+            return None;
+        }
+
+        let doublings = (stats.failures - threshold).min(30) as u32;
+        let lockout = TimeDelta::seconds(
+            self.lockout_base
+                .num_seconds()
+                .saturating_mul(1 << doublings)
+                .min(self.lockout_max.num_seconds()),
+        );
+        let remaining = (last_failure + lockout - Utc::now()).num_milliseconds();
+        (remaining > 0).then(|| (remaining + 999) / 1000)
+    }
+}
+
+/// Address of the client making the request
+pub struct ClientIp(pub String);
+
+impl FromRequestParts<AppState> for ClientIp {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        if state.throttle.trust_forwarded_for
+            && let Some(forwarded) = parts
+                .headers
+                .get("x-forwarded-for")
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.rsplit(',').next())
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+        {
+            return Ok(ClientIp(forwarded.to_string()));
+        }
+
+        let ip = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip().to_string())
+            .unwrap_or_else(|| "unknown".to_string());
+        Ok(ClientIp(ip))
+    }
+}
+
+/// Refuse the attempt if the client address or the account is locked out
+pub async fn check(
+    pool: &SqlitePool,
+    config: &ThrottleConfig,
+    ip: &str,
+    email: Option<&str>,
+) -> Result<(), AuthError> {
+    let since = Utc::now() - config.window;
+
+    let by_ip = db::failures_by_ip(pool, ip, since).await.map_err(|err| {
+        eprintln!("Database error: {}", err);
+        AuthError::Database
+    })?;
+    let mut retry_after = config.retry_after(&by_ip, config.max_attempts_per_ip);
+
+    if let Some(email) = email {
+        let by_email = db::failures_by_email(pool, email, since)
+            .await
+            .map_err(|err| {
+                eprintln!("Database error: {}", err);
+                AuthError::Database
+            })?;
+        retry_after = retry_after.max(config.retry_after(&by_email, config.max_attempts));
+    }
+
+    match retry_after {
+        Some(secs) => {
+            warn!(
+                ip,
+                email, "Refused attempt from locked out client or account"
+            );
+            Err(AuthError::TooManyAttempts(secs))
+        }
+        None => Ok(()),
+    }
+}
+
+/// Add an attempt to the audit log
+pub async fn record(
+    pool: &SqlitePool,
+    endpoint: &str,
+    ip: &str,
+    email: Option<&str>,
+    failure: Option<&str>,
+) {
+    if let Some(reason) = failure {
+        warn!(endpoint, ip, email, reason, "Failed authentication attempt");
+    }
+    if let Err(err) =
+        db::record_login_attempt(pool, endpoint, ip, email, failure.is_none(), failure).await
+    {
+        eprintln!("Database error: {}", err);
+    }
+}
+
+/// Check an email and password like [`authjwt::authenticate_password`], refusing
+/// locked out clients and accounts and recording the outcome. A correct password
+/// for an account with TOTP enabled only counts as a success once the second
+/// step is completed as well.
+pub async fn authenticate_password(
+    pool: &SqlitePool,
+    config: &ThrottleConfig,
+    endpoint: &str,
+    ip: &str,
+    email: &str,
+    password: String,
+) -> Result<User, AuthError> {
+    let email = email.trim().to_lowercase();
+    check(
+        pool,
+        config,
+        ip,
+        (!email.is_empty()).then_some(email.as_str()),
+    )
+    .await?;
+
+    let result = authjwt::authenticate_password(pool, &email, password).await;
+    let failure = match &result {
+        Ok(user) if user.totp_enabled() => return result,
+        Ok(_) => None,
+        Err(AuthError::WrongCredentials) => Some("wrong credentials"),
+        Err(AuthError::AccountDisabled) => Some("account disabled"),
+        Err(_) => return result,
+    };
+    record(pool, endpoint, ip, Some(&email), failure).await;
+    result
+}
+
+/// Finish a two-step login like [`totp::complete_challenge`], refusing locked
+/// out clients and accounts and recording the outcome
+pub async fn complete_challenge(
+    pool: &SqlitePool,
+    config: &ThrottleConfig,
+    endpoint: &str,
+    ip: &str,
+    challenge: &str,
+    code: &str,
+) -> Result<User, AuthError> {
+    let user = totp::challenge_user(pool, challenge).await?;
+    check(pool, config, ip, Some(&user.email)).await?;
+
+    let failure = match totp::complete_challenge(pool, challenge, &user, code).await {
+        Ok(()) => None,
+        Err(AuthError::WrongCredentials) => Some("wrong two-factor code"),
+        Err(e) => return Err(e),
+    };
+    record(pool, endpoint, ip, Some(&user.email), failure).await;
+    match failure {
+        None => Ok(user),
+        Some(_) => Err(AuthError::WrongCredentials),
+    }
+}
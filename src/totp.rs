@@ -0,0 +1,374 @@
+//! Two-factor authentication module for the Quotes Server.
+//!
+//! Implements time-based one-time passwords (RFC 6238) set up with an
+//! authenticator app, single-use recovery codes, and the challenge a password
+//! login has to complete with either of them before any tokens are issued.
+//!
+use crate::authjwt::{self, AuthError};
+use crate::db::{self, User};
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use base64::{Engine, engine::general_purpose::STANDARD};
+use chrono::{DateTime, TimeDelta, Utc};
+use data_encoding::BASE32_NOPAD;
+use hmac::{Hmac, Mac};
+use qrcode::{QrCode, render::svg};
+use serde::{Deserialize, Serialize};
+use sha1::Sha1;
+use sqlx::SqlitePool;
+use utoipa::ToSchema;
+
+/// Issuer shown next to the account in authenticator apps
+const ISSUER: &str = "Quotes Server";
+
+/// Seconds each code is valid for
+const PERIOD: i64 = 30;
+
+/// Digits in a code
+const DIGITS: u32 = 6;
+
+/// Codes from this many periods before or after the current one are accepted,
+/// to allow for clock drift
+const SKEW: i64 = 1;
+
+/// Recovery codes handed out when TOTP is enabled
+const RECOVERY_CODE_COUNT: usize = 10;
+
+/// How long the second step of a login may take
+const CHALLENGE_TTL: TimeDelta = TimeDelta::minutes(5);
+
+/// Secret and enrollment links for an authenticator app
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TotpEnrollment {
+    /// Base32 secret, for entering into the app by hand
+    #[schema(example = "JBSWY3DPEHPK3PXPJBSWY3DPEHPK3PXP")]
+    pub secret: String,
+    /// `otpauth://` URI carrying the secret
+    pub otpauth_uri: String,
+    /// The URI as a QR code, as an SVG `data:` URI
+    pub qr_code: String,
+}
+
+impl TotpEnrollment {
+    pub fn new(secret: String, account: &str) -> Self {
+        let otpauth_uri = format!(
+            "otpauth://totp/{issuer}:{account}?secret={secret}&issuer={issuer}&algorithm=SHA1&digits={DIGITS}&period={PERIOD}",
+            issuer = percent_encode(ISSUER),
+            account = percent_encode(account),
+        );
+        let qr_code = QrCode::new(otpauth_uri.as_bytes())
+            .map(|code| {
+                let image = code.render::<svg::Color>().min_dimensions(200, 200).build();
+                format!("data:image/svg+xml;base64,{}", STANDARD.encode(image))
+            })
+            .unwrap_or_default();
+
+        Self {
+            secret,
+            otpauth_uri,
+            qr_code,
+        }
+    }
+}
+
+/// Recovery codes, each usable once in place of a TOTP code
+#[derive(Debug, Serialize, ToSchema)]
+pub struct RecoveryCodes {
+    #[schema(example = json!(["k3f9q-2mz7x"]))]
+    pub recovery_codes: Vec<String>,
+}
+
+/// Code from the authenticator app, or a recovery code
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct TotpCodeRequest {
+    #[schema(example = "123456")]
+    pub code: String,
+}
+
+/// Returned by a login whose account has TOTP enabled
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TotpChallenge {
+    /// Token to send along with the code to `/auth/totp`
+    pub challenge: String,
+    /// Seconds until the challenge expires
+    #[schema(example = 300)]
+    pub expires_in: i64,
+}
+
+/// Second step of a login
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct TotpLoginRequest {
+    pub challenge: String,
+    /// Code from the authenticator app, or a recovery code
+    #[schema(example = "123456")]
+    pub code: String,
+}
+
+/// Percent-encode a value for use in a URI
+pub fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'@' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
+
+/// Generate a new random 160-bit secret, Base32 encoded
+pub fn generate_secret() -> String {
+    let mut bytes = [0u8; 20];
+    OsRng.fill_bytes(&mut bytes);
+    BASE32_NOPAD.encode(&bytes)
+}
+
+/// The code for a secret at the given time
+pub fn code_at(secret: &str, time: DateTime<Utc>) -> Option<String> {
+    let key = BASE32_NOPAD.decode(secret.as_bytes()).ok()?;
+    let step = time.timestamp().div_euclid(PERIOD);
+
+    let mut mac = Hmac::<Sha1>::new_from_slice(&key).ok()?;
+    mac.update(&step.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+
+    // Dynamic truncation as described in RFC 4226
+    let offset = usize::from(hash[hash.len() - 1] & 0x0f);
+    let binary = u32::from_be_bytes([
+        hash[offset] & 0x7f,
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]);
+    Some(format!(
+        "{:0width$}",
+        binary % 10u32.pow(DIGITS),
+        width = DIGITS as usize
+    ))
+}
+
+/// Check a code against a secret, returning the time step it belongs to
+pub fn verify_code(secret: &str, code: &str, now: DateTime<Utc>) -> Option<i64> {
+    let code: String = code.chars().filter(|c| !c.is_whitespace()).collect();
+    if code.len() != DIGITS as usize || !code.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    (-SKEW..=SKEW)
+        .map(|skew| now + TimeDelta::seconds(skew * PERIOD))
+        .find(|time| code_at(secret, *time).as_deref() == Some(code.as_str()))
+        .map(|time| time.timestamp().div_euclid(PERIOD))
+}
+
+/// Recovery codes are compared without dashes, spaces or case
+fn hash_recovery_code(code: &str) -> String {
+    let code: String = code
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .collect::<String>()
+        .to_lowercase();
+    authjwt::hash_token(&code)
+}
+
+fn generate_recovery_codes() -> Vec<String> {
+    (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let mut bytes = [0u8; 8];
+            OsRng.fill_bytes(&mut bytes);
+            let code = BASE32_NOPAD.encode(&bytes).to_lowercase();
+            format!("{}-{}", &code[..5], &code[5..10])
+        })
+        .collect()
+}
+
+/// Check a TOTP code or an unused recovery code for an account with TOTP
+/// enabled. Each TOTP code and recovery code is only accepted once.
+pub async fn verify_second_factor(
+    pool: &SqlitePool,
+    user: &User,
+    code: &str,
+) -> Result<bool, AuthError> {
+    let Some(secret) = db::get_totp_secret(pool, user.id)
+        .await
+        .map_err(|_| AuthError::Database)?
+    else {
+        return Ok(false);
+    };
+
+    if let Some(step) = verify_code(&secret, code, Utc::now()) {
+        return db::use_totp_step(pool, user.id, step)
+            .await
+            .map_err(|_| AuthError::Database);
+    }
+
+    db::use_recovery_code(pool, user.id, &hash_recovery_code(code))
+        .await
+        .map_err(|_| AuthError::Database)
+}
+
+/// Generate a new secret for an account, replacing any earlier unconfirmed one
+pub async fn begin_enrollment(pool: &SqlitePool, user: &User) -> Result<TotpEnrollment, AuthError> {
+    let secret = generate_secret();
+    let stored = db::set_totp_secret(pool, user.id, &secret)
+        .await
+        .map_err(|_| AuthError::Database)?;
+    if !stored {
+        return Err(AuthError::TotpAlreadyEnabled);
+    }
+    Ok(TotpEnrollment::new(secret, &user.email))
+}
+
+/// The enrollment started by [`begin_enrollment`] and not yet confirmed, if any
+pub async fn pending_enrollment(
+    pool: &SqlitePool,
+    user: &User,
+) -> Result<Option<TotpEnrollment>, AuthError> {
+    if user.totp_enabled() {
+        return Ok(None);
+    }
+    let secret = db::get_totp_secret(pool, user.id)
+        .await
+        .map_err(|_| AuthError::Database)?;
+    Ok(secret.map(|secret| TotpEnrollment::new(secret, &user.email)))
+}
+
+/// Enable TOTP once the app shows a matching code, returning new recovery codes
+pub async fn confirm_enrollment(
+    pool: &SqlitePool,
+    user: &User,
+    code: &str,
+) -> Result<RecoveryCodes, AuthError> {
+    if user.totp_enabled() {
+        return Err(AuthError::TotpAlreadyEnabled);
+    }
+    let secret = db::get_totp_secret(pool, user.id)
+        .await
+        .map_err(|_| AuthError::Database)?
+        .ok_or(AuthError::TotpNotEnabled)?;
+
+    let step = verify_code(&secret, code, Utc::now()).ok_or(AuthError::WrongCredentials)?;
+    let fresh = db::use_totp_step(pool, user.id, step)
+        .await
+        .map_err(|_| AuthError::Database)?;
+    if !fresh {
+        return Err(AuthError::WrongCredentials);
+    }
+
+    let recovery_codes = generate_recovery_codes();
+    let hashes: Vec<String> = recovery_codes
+        .iter()
+        .map(|code| hash_recovery_code(code))
+        .collect();
+    db::enable_totp(pool, user.id, &hashes)
+        .await
+        .map_err(|_| AuthError::Database)?;
+
+    Ok(RecoveryCodes { recovery_codes })
+}
+
+/// Turn TOTP off after checking a current code or recovery code
+pub async fn disable(pool: &SqlitePool, user: &User, code: &str) -> Result<(), AuthError> {
+    if !user.totp_enabled() {
+        return Err(AuthError::TotpNotEnabled);
+    }
+    if !verify_second_factor(pool, user, code).await? {
+        return Err(AuthError::WrongCredentials);
+    }
+    db::disable_totp(pool, user.id)
+        .await
+        .map_err(|_| AuthError::Database)
+}
+
+/// Hold a login that passed the password check until its second factor is given
+pub async fn start_challenge(pool: &SqlitePool, user: &User) -> Result<TotpChallenge, AuthError> {
+    let challenge = authjwt::random_token(32);
+    db::create_login_challenge(
+        pool,
+        &authjwt::hash_token(&challenge),
+        user.id,
+        Utc::now() + CHALLENGE_TTL,
+    )
+    .await
+    .map_err(|_| AuthError::Database)?;
+
+    Ok(TotpChallenge {
+        challenge,
+        expires_in: CHALLENGE_TTL.num_seconds(),
+    })
+}
+
+/// The account a pending challenge belongs to
+pub async fn challenge_user(pool: &SqlitePool, challenge: &str) -> Result<User, AuthError> {
+    let pending = db::get_login_challenge(pool, &authjwt::hash_token(challenge))
+        .await
+        .map_err(|_| AuthError::Database)?
+        .ok_or(AuthError::InvalidToken)?;
+    if pending.expires_at < Utc::now() {
+        return Err(AuthError::TokenExpired);
+    }
+
+    let user = db::get_user_by_id(pool, pending.user_id)
+        .await
+        .map_err(|_| AuthError::Database)?
+        .ok_or(AuthError::InvalidToken)?;
+    if user.disabled {
+        return Err(AuthError::AccountDisabled);
+    }
+    Ok(user)
+}
+
+/// Finish a login started by [`start_challenge`] for the challenge's account
+pub async fn complete_challenge(
+    pool: &SqlitePool,
+    challenge: &str,
+    user: &User,
+    code: &str,
+) -> Result<(), AuthError> {
+    if !verify_second_factor(pool, user, code).await? {
+        return Err(AuthError::WrongCredentials);
+    }
+
+    // Another request completed this challenge first
+    let pending = db::delete_login_challenge(pool, &authjwt::hash_token(challenge))
+        .await
+        .map_err(|_| AuthError::Database)?;
+    if !pending {
+        return Err(AuthError::InvalidToken);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rfc6238_vectors() {
+        // The SHA-1 secret from RFC 6238 appendix B, truncated to six digits
+        let secret = BASE32_NOPAD.encode(b"12345678901234567890");
+        let at = |timestamp| DateTime::from_timestamp(timestamp, 0).unwrap();
+
+        assert_eq!(code_at(&secret, at(59)).unwrap(), "287082");
+        assert_eq!(code_at(&secret, at(1111111109)).unwrap(), "081804");
+        assert_eq!(code_at(&secret, at(2000000000)).unwrap(), "279037");
+
+        let now = at(1111111109);
+        assert_eq!(verify_code(&secret, "081 804", now), Some(1111111109 / 30));
+        assert_eq!(
+            verify_code(&secret, &code_at(&secret, at(1111111139)).unwrap(), now),
+            Some(1111111139 / 30)
+        );
+        assert_eq!(verify_code(&secret, "000000", now), None);
+        assert_eq!(verify_code(&secret, "08180", now), None);
+    }
+
+    #[test]
+    fn test_enrollment_uri() {
+        let enrollment = TotpEnrollment::new("JBSWY3DPEHPK3PXP".to_string(), "ada@example.com");
+        assert_eq!(
+            enrollment.otpauth_uri,
+            "otpauth://totp/Quotes%20Server:ada@example.com?secret=JBSWY3DPEHPK3PXP&issuer=Quotes%20Server&algorithm=SHA1&digits=6&period=30"
+        );
+        assert!(enrollment.qr_code.starts_with("data:image/svg+xml;base64,"));
+    }
+}
@@ -0,0 +1,74 @@
+//! Trash purging module for the Quotes Server.
+//!
+//! Deleted quotes are kept in the trash, where they can be restored, for a
+//! configurable retention period. A background task wakes up periodically and
+//! permanently deletes the quotes that have been in the trash for longer.
+//!
+use crate::db;
+use crate::{env_or, env_time_delta};
+use chrono::{TimeDelta, Utc};
+use sqlx::SqlitePool;
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// How long deleted quotes are kept, and how often the trash is purged
+#[derive(Clone, Debug)]
+pub struct TrashConfig {
+    /// Time a quote stays in the trash before it is purged
+    pub retention: TimeDelta,
+    /// Time between purges
+    pub purge_interval: Duration,
+}
+
+impl Default for TrashConfig {
+    fn default() -> Self {
+        Self {
+            retention: TimeDelta::days(30),
+            purge_interval: Duration::from_secs(60 * 60),
+        }
+    }
+}
+
+impl TrashConfig {
+    /// Read the settings from `TRASH_RETENTION_DAYS` and `TRASH_PURGE_INTERVAL_SECS`,
+    /// falling back to the defaults for unset ones
+    pub fn from_env() -> Result<Self, Box<dyn std::error::Error>> {
+        let defaults = Self::default();
+        let purge_interval = env_or(
+            "TRASH_PURGE_INTERVAL_SECS",
+            defaults.purge_interval.as_secs(),
+        )?;
+        if purge_interval == 0 {
+            return Err("TRASH_PURGE_INTERVAL_SECS must be greater than zero".into());
+        }
+        Ok(Self {
+            retention: env_time_delta(
+                "TRASH_RETENTION_DAYS",
+                defaults.retention.num_days(),
+                TimeDelta::try_days,
+            )?,
+            purge_interval: Duration::from_secs(purge_interval),
+        })
+    }
+}
+
+/// Permanently delete the quotes that have been in the trash for longer than
+/// the retention period. Returns the number of quotes deleted.
+pub async fn purge_expired(pool: &SqlitePool, config: &TrashConfig) -> Result<u64, sqlx::Error> {
+    db::purge_trash(pool, Utc::now() - config.retention).await
+}
+
+/// Start the background task that purges the trash every `purge_interval`
+pub fn spawn_purge_task(pool: SqlitePool, config: TrashConfig) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(config.purge_interval);
+        loop {
+            interval.tick().await;
+            match purge_expired(&pool, &config).await {
+                Ok(0) => {}
+                Ok(purged) => info!("Purged {purged} quotes from the trash"),
+                Err(err) => warn!("Failed to purge the trash: {err}"),
+            }
+        }
+    })
+}